@@ -0,0 +1,232 @@
+use crate::repo::Repo;
+use log::error;
+use nexa_rs::nexa::{self, DeviceNumber};
+use pulse_rs::scheduler::Transmitter;
+use rollo_rs::blind::{Blind, TravelTimes};
+use rollo_rs::rollo::{self, RolloCode};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::Duration;
+
+// Which device is reached how is data in the repo. The transports are built
+// from it at startup and rebuilt whenever it changes, so adding a plug or a
+// blind is an insert and not a new build.
+
+const BLIND_TRAVEL_TIMES: TravelTimes = TravelTimes {
+    up: Duration::from_secs(30),
+    down: Duration::from_secs(27),
+};
+
+/// How a device is switched.
+#[derive(Clone)]
+pub enum Transport {
+    Nexa(nexa::Nexa, DeviceNumber),
+    Blind(Arc<Mutex<Blind>>),
+    /// A channel of the remote that moves several blinds, the devices given.
+    AllBlinds(rollo::Rollo, Vec<i64>),
+    Relay {
+        base_url: String,
+        relay_id: String,
+    },
+}
+
+#[derive(Clone)]
+pub struct Dispatch {
+    repo: Repo,
+    transmitter: Transmitter,
+    transports: Arc<RwLock<HashMap<i64, Transport>>>,
+    // the senders of the groups of the remotes, by name
+    groups: Arc<RwLock<Vec<(String, nexa::Nexa)>>>,
+    aliases: Arc<RwLock<HashMap<String, i64>>>,
+}
+
+impl Dispatch {
+    pub fn load(repo: Repo, transmitter: Transmitter) -> rusqlite::Result<Dispatch> {
+        let dispatch = Dispatch {
+            repo,
+            transmitter,
+            transports: Arc::default(),
+            groups: Arc::default(),
+            aliases: Arc::default(),
+        };
+        dispatch.reload()?;
+        Ok(dispatch)
+    }
+
+    /// Rebuilds the transports from the repo. Blinds that are still there
+    /// keep the position they were estimated at.
+    pub fn reload(&self) -> rusqlite::Result<()> {
+        let mut blinds: HashMap<String, Arc<Mutex<Blind>>> = self
+            .blinds()
+            .into_iter()
+            .map(|blind| {
+                let code = lock(&blind).rollo().code().to_string();
+                (code, blind)
+            })
+            .collect();
+        let mut transports = HashMap::new();
+
+        for transport in self.repo.get_nexa_transports()? {
+            let sender = nexa::Nexa::new(transport.sender_id, self.transmitter.clone());
+            match (sender, DeviceNumber::from_index(transport.unit)) {
+                (Ok(sender), Some(device_number)) => {
+                    transports.insert(transport.device_id, Transport::Nexa(sender, device_number));
+                }
+                _ => error!("Invalid Nexa transport {:?}", transport),
+            }
+        }
+        for transport in self.repo.get_rollo_transports()? {
+            let code: RolloCode = match transport.code.parse() {
+                Ok(code) => code,
+                Err(err) => {
+                    error!("Invalid Rollo transport {:?} ({})", transport, err);
+                    continue;
+                }
+            };
+            let rollo = rollo::Rollo::new(code, self.transmitter.clone());
+            // which blinds a channel moves is not in its code, the device
+            // references them instead
+            let moved = self.repo.get_references(transport.device_id)?;
+            let device = match blinds.remove(&code.to_string()) {
+                _ if !moved.is_empty() => Transport::AllBlinds(rollo, moved),
+                Some(blind) => Transport::Blind(blind),
+                None => {
+                    let position = self.repo.get_blind_position(&transport.code)?;
+                    let blind = Blind::new(rollo, BLIND_TRAVEL_TIMES, position.unwrap_or(0));
+                    Transport::Blind(Arc::new(Mutex::new(blind)))
+                }
+            };
+            transports.insert(transport.device_id, device);
+        }
+        for transport in self.repo.get_relay_transports()? {
+            transports.insert(
+                transport.device_id,
+                Transport::Relay {
+                    base_url: transport.base_url,
+                    relay_id: transport.relay_id,
+                },
+            );
+        }
+
+        let mut groups = vec![];
+        for group in self.repo.get_nexa_groups()? {
+            match nexa::Nexa::new(group.sender_id, self.transmitter.clone()) {
+                Ok(sender) => groups.push((group.name, sender)),
+                Err(_) => error!("Invalid Nexa group {:?}", group),
+            }
+        }
+        let aliases = self
+            .repo
+            .get_device_aliases()?
+            .into_iter()
+            .map(|alias| (alias.name, alias.device_id))
+            .collect();
+
+        *self
+            .transports
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = transports;
+        *self.groups.write().unwrap_or_else(PoisonError::into_inner) = groups;
+        *self.aliases.write().unwrap_or_else(PoisonError::into_inner) = aliases;
+        Ok(())
+    }
+
+    /// The id of the device a name in the url stands for, which is either
+    /// the id itself or an alias.
+    pub fn device_id(&self, name: &str) -> Option<i64> {
+        name.parse().ok().or_else(|| {
+            self.aliases
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(name)
+                .copied()
+        })
+    }
+
+    pub fn get(&self, device_id: i64) -> Option<Transport> {
+        self.transports
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&device_id)
+            .cloned()
+    }
+
+    /// The senders of a group of the remotes, or of all of them for "all".
+    pub fn group(&self, name: &str) -> Vec<nexa::Nexa> {
+        self.groups
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|(group, _)| name == "all" || group == name)
+            .map(|(_, sender)| sender.clone())
+            .collect()
+    }
+
+    fn blinds(&self) -> Vec<Arc<Mutex<Blind>>> {
+        self.transports
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .filter_map(|transport| match transport {
+                Transport::Blind(blind) => Some(blind.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Locks a blind. A panic elsewhere cannot leave the estimate worse than it
+/// already is.
+pub fn lock(blind: &Mutex<Blind>) -> std::sync::MutexGuard<'_, Blind> {
+    blind.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[test]
+fn test_dispatch_from_repo() {
+    use crate::repo::Device;
+    use pulse_rs::pulse::Recorder;
+    use rusqlite::Connection;
+
+    let repo = Repo::new("test_dispatch_from_repo.db");
+    repo.assure_created().unwrap();
+    repo.ensure_updated().unwrap();
+    // a fresh database already has the blind, as device 1
+    for name in ["lamp", "all blinds", "heater", "broken"] {
+        repo.add_device(&mut Device::new(name, 1, false)).unwrap();
+    }
+    let conn = Connection::open("test_dispatch_from_repo.db").unwrap();
+    conn.execute_batch(
+        "INSERT INTO nexa_devices VALUES(2, 50331650, 2), (5, 50331650, 17);
+        INSERT INTO rollo_devices VALUES(3, 'FQ1Q011000Q000000');
+        INSERT INTO device_ref_device(device_id, reference_device_id) VALUES(3, 1);
+        INSERT INTO relay_devices VALUES(4, 'http://relay', '4');",
+    )
+    .unwrap();
+    let dispatch = Dispatch::load(repo.clone(), Transmitter::spawn(Recorder::new())).unwrap();
+
+    assert!(matches!(dispatch.get(2), Some(Transport::Nexa(_, n)) if n.index() == 2));
+    assert!(matches!(dispatch.get(3), Some(Transport::AllBlinds(_, blinds)) if blinds == [1]));
+    assert!(matches!(
+        dispatch.get(4),
+        Some(Transport::Relay { base_url, relay_id }) if base_url == "http://relay" && relay_id == "4"
+    ));
+    assert!(dispatch.get(5).is_none());
+    assert!(dispatch.device_id("4") == Some(4) && dispatch.device_id("r") == Some(1));
+    assert!(dispatch.device_id("lamp").is_none());
+    let m3 = dispatch.group("m3");
+    assert!(m3.len() == 1 && m3[0].sender_id().value() == 50331648);
+    assert!(dispatch.group("all").len() == 3);
+    assert!(dispatch.group("m4").is_empty());
+    let blind = match dispatch.get(1) {
+        Some(Transport::Blind(blind)) => blind,
+        _ => panic!("device 1 is not a blind"),
+    };
+    blind.lock().unwrap().move_to(100);
+
+    // the blind carries on where it was after a reload
+    conn.execute("DELETE FROM relay_devices", []).unwrap();
+    dispatch.reload().unwrap();
+    assert!(dispatch.get(4).is_none());
+    assert!(matches!(dispatch.get(1), Some(Transport::Blind(b)) if Arc::ptr_eq(&b, &blind)));
+    std::fs::remove_file("test_dispatch_from_repo.db").unwrap();
+}
@@ -3,36 +3,59 @@
 extern crate rocket;
 use log::{error, info, trace, warn};
 extern crate log4rs;
+use dispatch::Transport;
 use nexa_rs::nexa;
+use nexa_rs::nexa::{Channel, DeviceNumber, Unit};
+use pulse_rs::pulse::Jitter;
+use pulse_rs::receiver::Receiver;
+use pulse_rs::scheduler::{Transmission, TransmitError, Transmitter};
+use pulse_rs::tristate;
+use rollo_rs::blind::Blind;
 use rollo_rs::rollo;
 
 use std::thread;
+mod dispatch;
 mod repo;
+mod simulated;
 
 use log::LevelFilter;
 use log4rs::append::file::FileAppender;
 use log4rs::encode::pattern::PatternEncoder;
 use rocket::config::{Config, Environment};
+use rocket::http::Status;
+use rocket::response::status::{Created, Custom, NoContent};
 use rocket_contrib::json::Json;
 use rocket_contrib::serve::StaticFiles;
 use rppal::gpio::Gpio;
+use serde::Deserialize;
+use std::env;
 use std::error::Error;
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use rocket::State;
 
+// Edges later than this are likely to garble a frame for the receivers
+const JITTER_WARN_US: u64 = 100;
+// Repeats of the same remote command within this window are one press
+const REPEAT_WINDOW: Duration = Duration::from_secs(1);
+
 #[derive(Clone)]
-struct SenderState<'a> {
-    sender_one: nexa::Nexa<'a>,
-    sender_two: nexa::Nexa<'a>,
-    sender_three: nexa::Nexa<'a>,
-    sender_four: nexa::Nexa<'a>,
-    sender_five: nexa::Nexa<'a>,
-    rollo: rollo::Rollo<'a>,
+struct SenderState {
+    devices: dispatch::Dispatch,
+    transmitter: Transmitter,
+    // set when running without a Pi
+    simulated: Option<simulated::SimulatedRadio>,
     repo: repo::Repo,
 }
 
+#[derive(Deserialize)]
+struct RawCode {
+    protocol: String,
+    code: String,
+}
+
 fn call_external_device(base_url: &str, device_id: &str, mode: &str) -> Result<(), Box<dyn Error>> {
     let url = format!("{}/{}/{}", base_url, device_id, mode);
 
@@ -42,98 +65,240 @@ fn call_external_device(base_url: &str, device_id: &str, mode: &str) -> Result<(
     }
 }
 
-fn get_device_number_from_id<'b, 'a>(
-    id: &'b str,
-    state: &'a SenderState,
-) -> Option<(&'a nexa::Nexa<'a>, nexa::DeviceNumber)> {
-    match id {
-        "1" => Some((&state.sender_one, nexa::DeviceNumber::One)),
-        "2" => Some((&state.sender_one, nexa::DeviceNumber::Two)),
-        "3" => Some((&state.sender_one, nexa::DeviceNumber::Three)),
-        "4" => Some((&state.sender_two, nexa::DeviceNumber::One)),
-        "5" => Some((&state.sender_two, nexa::DeviceNumber::Two)),
-        "6" => Some((&state.sender_two, nexa::DeviceNumber::Three)),
-        "7" => Some((&state.sender_three, nexa::DeviceNumber::One)),
-        "8" => Some((&state.sender_three, nexa::DeviceNumber::Two)),
-        "9" => Some((&state.sender_three, nexa::DeviceNumber::Three)),
-        "10" => Some((&state.sender_four, nexa::DeviceNumber::One)),
-        "13" => Some((&state.sender_five, nexa::DeviceNumber::One)),
-        "14" => Some((&state.sender_five, nexa::DeviceNumber::Two)),
-        "15" => Some((&state.sender_five, nexa::DeviceNumber::Three)),
-
+fn get_nexa_device(
+    device_id: i64,
+    state: &SenderState,
+) -> Option<(nexa::Nexa, nexa::DeviceNumber)> {
+    match state.devices.get(device_id) {
+        Some(Transport::Nexa(sender, device_number)) => Some((sender, device_number)),
         _ => None,
     }
 }
 
+// A sender id neither the remotes nor other paired devices use
+fn unused_sender_id(state: &SenderState) -> Result<nexa::SenderId, Box<dyn Error>> {
+    let used = state.repo.get_nexa_sender_ids()?;
+    loop {
+        let sender_id = nexa::SenderId::random();
+        if !used.contains(&sender_id.value()) {
+            return Ok(sender_id);
+        }
+    }
+}
+
+fn wait_for_transmission(
+    device_name: &str,
+    transmission: Transmission,
+) -> Result<Jitter, TransmitError> {
+    match transmission.wait() {
+        Ok(jitter) if jitter.max_us > JITTER_WARN_US => {
+            warn!("Transmission to {} was late ({})", device_name, jitter);
+            Ok(jitter)
+        }
+        Ok(jitter) => {
+            trace!("Transmitted to {} ({})", device_name, jitter);
+            Ok(jitter)
+        }
+        Err(err) => {
+            error!("Could not transmit to {} ({})", device_name, err);
+            Err(err)
+        }
+    }
+}
+
+/// Something wrong with the request itself, such as a missing or unknown
+/// parameter.
+#[derive(Debug)]
+struct RequestError(String);
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for RequestError {}
+
+fn request_error(message: impl Into<String>) -> Box<dyn Error> {
+    Box::new(RequestError(message.into()))
+}
+
+/// No device or group goes by the name in the request.
+#[derive(Debug)]
+struct UnknownDevice(String);
+
+impl fmt::Display for UnknownDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown device {}", self.0)
+    }
+}
+
+impl Error for UnknownDevice {}
+
+// Mistakes in the request are the client's fault, anything else is ours.
+fn error_status(err: &(dyn Error + 'static)) -> Status {
+    if err.is::<RequestError>() {
+        return Status::BadRequest;
+    }
+    if err.is::<UnknownDevice>() {
+        return Status::NotFound;
+    }
+    match err.downcast_ref::<nexa::NexaError>() {
+        Some(nexa::NexaError::InvalidDimLevel(_)) => Status::BadRequest,
+        _ => Status::InternalServerError,
+    }
+}
+
 fn set_device_mode(
     device_name: &str,
     mode: &str,
+    level: Option<u8>,
     sender_state: &SenderState,
 ) -> Result<(), Box<dyn Error>> {
-    if let Some((device, device_number)) = get_device_number_from_id(device_name, sender_state) {
-        match mode {
-            "on" => device.turn_device_on(device_number),
-            "off" => device.turn_device_off(device_number),
-            _ => {}
+    let transport = sender_state
+        .devices
+        .device_id(device_name)
+        .and_then(|device_id| sender_state.devices.get(device_id));
+    match transport {
+        Some(Transport::Nexa(device, device_number)) => {
+            let transmission = match mode {
+                "on" => device.turn_device_on(device_number),
+                "off" => device.turn_device_off(device_number),
+                "dim" => match level {
+                    Some(level) => device.set_dim_level(device_number, level)?,
+                    None => return Err(request_error("Missing dim level")),
+                },
+                _ => return Err(request_error(format!("Unknown mode {}", mode))),
+            };
+            wait_for_transmission(device_name, transmission)?;
         }
-    }
-    match device_name {
-        "all" if (mode == "on") => {
-            sender_state.sender_one.turn_group_on();
-            sender_state.sender_two.turn_group_on();
-            sender_state.sender_three.turn_group_on();
+        Some(Transport::Blind(blind)) => {
+            set_blind_mode(device_name, &blind, mode, level, sender_state)?
         }
-        "all" if (mode == "off") => {
-            sender_state.sender_one.turn_group_off();
-            sender_state.sender_two.turn_group_off();
-            sender_state.sender_three.turn_group_off();
+        Some(Transport::AllBlinds(rollo, blinds)) => {
+            set_all_blinds_mode(device_name, &rollo, &blinds, mode, sender_state)?
         }
-        "m1" if (mode == "on") => sender_state.sender_one.turn_group_on(),
-        "m1" if (mode == "off") => sender_state.sender_one.turn_group_off(),
-        "m2" if (mode == "on") => sender_state.sender_two.turn_group_on(),
-        "m2" if (mode == "off") => sender_state.sender_two.turn_group_off(),
-        "m3" if (mode == "on") => sender_state.sender_two.turn_group_off(),
-        "m3" if (mode == "off") => sender_state.sender_two.turn_group_off(),
-        "11" => call_external_device("http://192.168.10.124", "4", mode).unwrap(),
-        "12" => call_external_device("http://192.168.10.124", "5", mode).unwrap(),
-        "r" if (mode == "up") => sender_state.rollo.send(rollo::Direction::UP),
-        "r" if (mode == "down") => sender_state.rollo.send(rollo::Direction::DOWN),
-        "r" if (mode == "pause") => sender_state.rollo.send(rollo::Direction::PAUSE),
-
-        _ => println!("Unknown"),
+        Some(Transport::Relay { base_url, relay_id }) => {
+            call_external_device(&base_url, &relay_id, mode)?
+        }
+        None => set_group_mode(device_name, mode, sender_state)?,
+    }
+    Ok(())
+}
+
+fn set_group_mode(
+    device_name: &str,
+    mode: &str,
+    sender_state: &SenderState,
+) -> Result<(), Box<dyn Error>> {
+    let senders = sender_state.devices.group(device_name);
+    if senders.is_empty() {
+        return Err(Box::new(UnknownDevice(device_name.to_string())));
+    }
+    for sender in senders {
+        let transmission = match mode {
+            "on" => sender.turn_group_on(),
+            "off" => sender.turn_group_off(),
+            _ => return Err(request_error(format!("Unknown mode {}", mode))),
+        };
+        wait_for_transmission(device_name, transmission)?;
+    }
+    Ok(())
+}
+
+fn set_blind_mode(
+    device_name: &str,
+    blind: &Mutex<Blind>,
+    mode: &str,
+    level: Option<u8>,
+    sender_state: &SenderState,
+) -> Result<(), Box<dyn Error>> {
+    // the lock is only held to send, a move is stopped by the blind's timer
+    let (transmission, code, destination) = {
+        let mut blind = dispatch::lock(blind);
+        let transmission = match mode {
+            "up" => Some(blind.send(rollo::Direction::UP)),
+            "down" => Some(blind.send(rollo::Direction::DOWN)),
+            "pause" => Some(blind.send(rollo::Direction::PAUSE)),
+            "move" => blind.move_to(level.ok_or_else(|| request_error("Missing position"))?),
+            _ => return Err(request_error(format!("Unknown mode {}", mode))),
+        };
+        (transmission, blind.rollo().code(), blind.destination())
+    };
+    if let Some(transmission) = transmission {
+        wait_for_transmission(device_name, transmission)?;
+    }
+    sender_state
+        .repo
+        .set_blind_position(&code.to_string(), destination)?;
+    Ok(())
+}
+
+fn set_all_blinds_mode(
+    device_name: &str,
+    all_blinds: &rollo::Rollo,
+    blinds: &[i64],
+    mode: &str,
+    sender_state: &SenderState,
+) -> Result<(), Box<dyn Error>> {
+    let direction = match mode {
+        "up" => rollo::Direction::UP,
+        "down" => rollo::Direction::DOWN,
+        "pause" => rollo::Direction::PAUSE,
+        _ => return Err(request_error(format!("Unknown mode {}", mode))),
+    };
+    wait_for_transmission(device_name, all_blinds.send(direction))?;
+    for &device_id in blinds {
+        let blind = match sender_state.devices.get(device_id) {
+            Some(Transport::Blind(blind)) => blind,
+            _ => continue,
+        };
+        let mut blind = dispatch::lock(&blind);
+        blind.record(direction);
+        sender_state
+            .repo
+            .set_blind_position(&blind.rollo().code().to_string(), blind.destination())?;
     }
     Ok(())
 }
 
-#[get("/<device>?<mode>&<delay>")]
+#[get("/<device>?<mode>&<delay>&<level>")]
 fn set_device(
     device: String,
     mode: String,
     delay: Option<u64>,
+    level: Option<String>,
     sender_state: State<SenderState>,
-) -> String {
+) -> Result<String, Custom<String>> {
+    // an Option<u8> would silently be None for anything that is not a number
+    let level = match level.map(|level| level.parse::<u8>()) {
+        Some(Ok(level)) => Some(level),
+        Some(Err(_)) => return Err(Custom(Status::BadRequest, "Invalid level".to_string())),
+        None => None,
+    };
     match delay {
         Some(x) if x > 0 => {
             info!("Delay was set to {} for {}", x, device);
             let sender = sender_state.inner().clone();
             thread::spawn(move || {
                 thread::sleep(Duration::from_secs(x));
-                match set_device_mode(device.as_ref(), "off", &sender) {
+                match set_device_mode(device.as_ref(), "off", None, &sender) {
                     Ok(_) => info!("Device {} was turned off", device),
                     Err(x) => warn!("Could not turn {} off ({})", device, x),
                 }
             });
-            "Success".to_string()
+            Ok("Success".to_string())
         }
         None | Some(_) => {
             info!("Setting {} to {}", device, mode);
             match set_device_mode(
                 device.as_ref(),
                 mode.as_ref(),
+                level,
                 &sender_state.inner().clone(),
             ) {
-                Ok(_) => "Success".to_string(),
-                Err(x) => x.to_string(),
+                Ok(_) => Ok("Success".to_string()),
+                Err(x) => Err(Custom(error_status(&*x), x.to_string())),
             }
         }
     }
@@ -145,64 +310,446 @@ fn post_device(
     mode: String,
     delay: Option<u64>,
     sender_state: State<SenderState>,
-) -> Option<Json<repo::Device>> {
-    return match sender_state.repo.get_device(device_id).as_mut() {
-        Ok(None) => return None,
-        Ok(Some(device)) => {
-            return match delay {
-                Some(x) if x > 0 => {
-                    let sender = sender_state.inner().clone();
-                    let device_str: String = device_id.to_string();
-                    let d = device.clone();
-                    thread::spawn(move || {
-                        thread::sleep(Duration::from_secs(x));
-                        match set_device_mode(&device_str, "off", &sender) {
-                            Ok(_) => {
-                                sender.repo.update_device(&d).unwrap_or_else(|e| {
-                                    error!("Failed to update {}", e);
-                                    true
-                                });
-                            }
-                            Err(err) => {
-                                error!(
-                                    "Could not turn {} off with delay ({}) {}",
-                                    device_str, x, err
-                                );
-                                // true
-                            }
-                        }
-                    });
-                    Some(Json(device.clone()))
-                }
-                None | Some(_) => match mode.as_ref() {
-                    "on" => {
-                        device.current_state = true;
-                        sender_state.repo.update_device(device).unwrap();
-                        set_device_mode(device_id.to_string().as_ref(), "on", &sender_state)
-                            .unwrap();
-                        Some(Json(device.clone()))
+) -> Result<Option<Json<repo::Device>>, Custom<String>> {
+    let mut device = match sender_state.repo.get_device(device_id) {
+        Ok(Some(device)) => device,
+        Ok(None) => return Ok(None),
+        Err(x) => {
+            error!("Error: {}", x);
+            return Err(Custom(Status::InternalServerError, x.to_string()));
+        }
+    };
+    match delay {
+        Some(x) if x > 0 => {
+            let sender = sender_state.inner().clone();
+            let device_str: String = device_id.to_string();
+            let d = device.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_secs(x));
+                match set_device_mode(&device_str, "off", None, &sender) {
+                    Ok(_) => {
+                        sender.repo.update_device(&d).unwrap_or_else(|e| {
+                            error!("Failed to update {}", e);
+                            true
+                        });
                     }
-                    "off" => {
-                        device.current_state = false;
-                        sender_state.repo.update_device(device).unwrap();
-                        set_device_mode(device_id.to_string().as_ref(), "off", &sender_state)
-                            .unwrap();
-                        Some(Json(device.clone()))
+                    Err(err) => {
+                        error!(
+                            "Could not turn {} off with delay ({}) {}",
+                            device_str, x, err
+                        );
                     }
-                    _ => None,
-                },
-            };
+                }
+            });
+            Ok(Some(Json(device)))
         }
-        Err(x) => {
-            error!("Error: {}", x);
-            None
+        None | Some(_) => match mode.as_ref() {
+            "on" | "off" => {
+                set_device_mode(&device_id.to_string(), &mode, None, &sender_state)
+                    .map_err(|x| Custom(error_status(&*x), x.to_string()))?;
+                device.current_state = mode == "on";
+                sender_state.repo.update_device(&device).map_err(|x| {
+                    error!("Failed to update {}", x);
+                    Custom(Status::InternalServerError, x.to_string())
+                })?;
+                Ok(Some(Json(device)))
+            }
+            _ => Ok(None),
+        },
+    }
+}
+
+// Names tell devices apart in the app, so they have to be unique.
+fn validate_device(
+    device: &repo::Device,
+    sender_state: &SenderState,
+) -> Result<(), Custom<String>> {
+    if device.name.is_empty() || device.name.chars().count() > 100 {
+        return Err(Custom(
+            Status::BadRequest,
+            "Name must be 1 to 100 characters".to_string(),
+        ));
+    }
+    match sender_state.repo.get_group_details(device.group_id) {
+        Ok(Some(_)) => {}
+        Ok(None) if device.group_id == repo::NO_GROUP => {}
+        Ok(None) => {
+            return Err(Custom(
+                Status::BadRequest,
+                format!("Unknown group {}", device.group_id),
+            ))
+        }
+        Err(x) => return Err(Custom(Status::InternalServerError, x.to_string())),
+    }
+    match sender_state.repo.get_device_id_by_name(&device.name) {
+        Ok(Some(id)) if id != device.id => Err(Custom(
+            Status::Conflict,
+            format!("There already is a device named {}", device.name),
+        )),
+        Ok(_) => Ok(()),
+        Err(x) => Err(Custom(Status::InternalServerError, x.to_string())),
+    }
+}
+
+#[get("/<device_id>")]
+fn get_device(
+    device_id: i64,
+    sender_state: State<SenderState>,
+) -> Result<Option<Json<repo::Device>>, Custom<String>> {
+    match sender_state.repo.get_device(device_id) {
+        Ok(device) => Ok(device.map(Json)),
+        Err(x) => Err(Custom(Status::InternalServerError, x.to_string())),
+    }
+}
+
+#[post("/", format = "json", data = "<device>")]
+fn create_device(
+    device: Json<repo::Device>,
+    sender_state: State<SenderState>,
+) -> Result<Created<Json<repo::Device>>, Custom<String>> {
+    let mut device = device.into_inner();
+    device.id = 0;
+    device.name = device.name.trim().to_string();
+    validate_device(&device, &sender_state)?;
+    sender_state
+        .repo
+        .add_device(&mut device)
+        .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+    info!("Added device {} ({})", device.id, device.name);
+    Ok(Created(
+        format!("/api/devices/{}", device.id),
+        Some(Json(device)),
+    ))
+}
+
+/// Renames a device or moves it to another group.
+#[put("/<device_id>", format = "json", data = "<device>")]
+fn update_device(
+    device_id: i64,
+    device: Json<repo::Device>,
+    sender_state: State<SenderState>,
+) -> Result<Option<Json<repo::Device>>, Custom<String>> {
+    let mut device = device.into_inner();
+    device.id = device_id;
+    device.name = device.name.trim().to_string();
+    match sender_state.repo.get_device(device_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => return Ok(None),
+        Err(x) => return Err(Custom(Status::InternalServerError, x.to_string())),
+    }
+    validate_device(&device, &sender_state)?;
+    sender_state
+        .repo
+        .update_device_details(&device)
+        .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+    info!("Updated device {} ({})", device.id, device.name);
+    match sender_state.repo.get_device(device_id) {
+        Ok(device) => Ok(device.map(Json)),
+        Err(x) => Err(Custom(Status::InternalServerError, x.to_string())),
+    }
+}
+
+#[delete("/<device_id>")]
+fn delete_device(
+    device_id: i64,
+    sender_state: State<SenderState>,
+) -> Result<Option<NoContent>, Custom<String>> {
+    let deleted = sender_state
+        .repo
+        .delete_device(device_id)
+        .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+    if !deleted {
+        return Ok(None);
+    }
+    info!("Deleted device {}", device_id);
+    // the transport went with the device
+    sender_state
+        .devices
+        .reload()
+        .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+    Ok(Some(NoContent))
+}
+
+fn validate_group(group: &repo::Group, sender_state: &SenderState) -> Result<(), Custom<String>> {
+    if group.name.is_empty() || group.name.chars().count() > 100 {
+        return Err(Custom(
+            Status::BadRequest,
+            "Name must be 1 to 100 characters".to_string(),
+        ));
+    }
+    match sender_state.repo.get_groups() {
+        Ok(groups)
+            if groups
+                .iter()
+                .any(|g| g.name == group.name && g.id != group.id) =>
+        {
+            Err(Custom(
+                Status::Conflict,
+                format!("There already is a group named {}", group.name),
+            ))
+        }
+        Ok(_) => Ok(()),
+        Err(x) => Err(Custom(Status::InternalServerError, x.to_string())),
+    }
+}
+
+#[get("/")]
+fn get_groups(sender_state: State<SenderState>) -> Result<Json<Vec<repo::Group>>, Custom<String>> {
+    match sender_state.repo.get_groups() {
+        Ok(groups) => Ok(Json(groups)),
+        Err(x) => Err(Custom(Status::InternalServerError, x.to_string())),
+    }
+}
+
+#[post("/", format = "json", data = "<group>")]
+fn create_group(
+    group: Json<repo::Group>,
+    sender_state: State<SenderState>,
+) -> Result<Created<Json<repo::Group>>, Custom<String>> {
+    let mut group = group.into_inner();
+    group.id = 0;
+    group.name = group.name.trim().to_string();
+    validate_group(&group, &sender_state)?;
+    sender_state
+        .repo
+        .add_group(&mut group)
+        .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+    info!("Added group {} ({})", group.id, group.name);
+    Ok(Created(
+        format!("/api/groups/{}", group.id),
+        Some(Json(group)),
+    ))
+}
+
+#[put("/<group_id>", format = "json", data = "<group>")]
+fn update_group(
+    group_id: i64,
+    group: Json<repo::Group>,
+    sender_state: State<SenderState>,
+) -> Result<Option<Json<repo::Group>>, Custom<String>> {
+    let mut group = group.into_inner();
+    group.id = group_id;
+    group.name = group.name.trim().to_string();
+    validate_group(&group, &sender_state)?;
+    let updated = sender_state
+        .repo
+        .update_group(&group)
+        .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+    Ok(if updated { Some(Json(group)) } else { None })
+}
+
+/// Only empty groups can be deleted, devices are never left in a group that
+/// does not exist.
+#[delete("/<group_id>")]
+fn delete_group(
+    group_id: i64,
+    sender_state: State<SenderState>,
+) -> Result<Option<NoContent>, Custom<String>> {
+    let members = sender_state
+        .repo
+        .get_group(group_id)
+        .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+    if !members.is_empty() {
+        return Err(Custom(
+            Status::Conflict,
+            format!("Group {} still has {} devices", group_id, members.len()),
+        ));
+    }
+    let deleted = sender_state
+        .repo
+        .delete_group(group_id)
+        .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+    Ok(if deleted { Some(NoContent) } else { None })
+}
+
+/// Switches every device in the group and returns them with their new
+/// state. Devices that could not be switched keep the state they had.
+#[post("/<group_id>?<mode>")]
+fn switch_group(
+    group_id: i64,
+    mode: String,
+    sender_state: State<SenderState>,
+) -> Result<Option<Json<Vec<repo::Device>>>, Custom<String>> {
+    if mode != "on" && mode != "off" {
+        return Err(Custom(Status::BadRequest, format!("Unknown mode {}", mode)));
+    }
+    match sender_state.repo.get_group_details(group_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => return Ok(None),
+        Err(x) => return Err(Custom(Status::InternalServerError, x.to_string())),
+    }
+    let mut devices = sender_state
+        .repo
+        .get_group(group_id)
+        .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+    info!("Setting group {} to {}", group_id, mode);
+
+    let mut failed = vec![];
+    for device in devices.iter_mut() {
+        if let Err(err) = set_device_mode(&device.id.to_string(), &mode, None, &sender_state) {
+            warn!("Could not switch {} ({})", device.id, err);
+            failed.push(device.id.to_string());
+            continue;
+        }
+        device.current_state = mode == "on";
+    }
+    sender_state
+        .repo
+        .update_devices(&devices)
+        .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+    if !failed.is_empty() {
+        return Err(Custom(
+            Status::InternalServerError,
+            format!("Could not switch devices {}", failed.join(", ")),
+        ));
+    }
+    Ok(Some(Json(devices)))
+}
+
+/// Pairs a self-learning receiver, which has to be powered up just before.
+/// Devices without a sender are given a new one on the first unit.
+#[post("/<device_id>/pair")]
+fn pair_device(
+    device_id: i64,
+    sender_state: State<SenderState>,
+) -> Result<Option<Json<repo::NexaTransport>>, Custom<String>> {
+    match sender_state.repo.get_device(device_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => return Ok(None),
+        Err(x) => return Err(Custom(Status::InternalServerError, x.to_string())),
+    }
+    let device_name = device_id.to_string();
+    let (sender, device_number) = match sender_state.devices.get(device_id) {
+        Some(Transport::Nexa(sender, device_number)) => (sender, device_number),
+        Some(_) => {
+            return Err(Custom(
+                Status::BadRequest,
+                format!("Device {} is not a Nexa device", device_id),
+            ))
         }
+        None => {
+            let sender_id = unused_sender_id(&sender_state)
+                .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+            sender_state
+                .repo
+                .add_generated_nexa_sender(sender_id.value())
+                .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+            let sender = nexa::Nexa::new(sender_id.value(), sender_state.transmitter.clone())
+                .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+            (sender, DeviceNumber::new(Channel::One, Unit::One))
+        }
+    };
+    let transport = repo::NexaTransport {
+        device_id,
+        sender_id: sender.sender_id().value(),
+        unit: device_number.index(),
+    };
+    info!("Pairing {} with sender {}", device_name, sender.sender_id());
+    wait_for_transmission(&device_name, sender.pair(device_number))
+        .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+    sender_state
+        .repo
+        .set_nexa_transport(&transport)
+        .and_then(|_| sender_state.devices.reload())
+        .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+    Ok(Some(Json(transport)))
+}
+
+/// Makes a receiver in learning mode forget the sender of the device. The
+/// device keeps the sender of a remote, only one made up by pair_device is
+/// forgotten as well.
+#[post("/<device_id>/unpair")]
+fn unpair_device(
+    device_id: i64,
+    sender_state: State<SenderState>,
+) -> Result<Option<Json<repo::NexaTransport>>, Custom<String>> {
+    match sender_state.repo.get_device(device_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => return Ok(None),
+        Err(x) => return Err(Custom(Status::InternalServerError, x.to_string())),
+    }
+    let device_name = device_id.to_string();
+    let (sender, device_number) = match get_nexa_device(device_id, &sender_state) {
+        Some(paired) => paired,
+        None => {
+            return Err(Custom(
+                Status::BadRequest,
+                format!("Device {} is not a Nexa device", device_id),
+            ))
+        }
+    };
+    let transport = repo::NexaTransport {
+        device_id,
+        sender_id: sender.sender_id().value(),
+        unit: device_number.index(),
     };
+    info!(
+        "Unpairing {} from sender {}",
+        device_name,
+        sender.sender_id()
+    );
+    wait_for_transmission(&device_name, sender.unpair(device_number))
+        .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+    let generated = sender_state
+        .repo
+        .is_generated_nexa_sender(transport.sender_id)
+        .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+    if generated {
+        sender_state
+            .repo
+            .delete_nexa_transport(device_id)
+            .and_then(|_| sender_state.devices.reload())
+            .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+    }
+    Ok(Some(Json(transport)))
+}
+
+fn raw_protocol(name: &str) -> Option<tristate::Protocol> {
+    match name {
+        "pt2262" => Some(tristate::PT2262),
+        "rollo" => Some(rollo::PROTOCOL),
+        _ => None,
+    }
+}
+
+#[post("/raw", format = "json", data = "<raw>")]
+fn post_raw(
+    raw: Json<RawCode>,
+    sender_state: State<SenderState>,
+) -> Result<String, Custom<String>> {
+    let protocol = raw_protocol(&raw.protocol).ok_or_else(|| {
+        Custom(
+            Status::BadRequest,
+            format!("Unknown protocol {}", raw.protocol),
+        )
+    })?;
+    let code = tristate::parse(&raw.code)
+        .ok_or_else(|| Custom(Status::BadRequest, format!("Invalid code {}", raw.code)))?;
+    info!("Sending {} code {}", raw.protocol, raw.code);
+    let frame = tristate::encode(&code, &protocol);
+    wait_for_transmission(&raw.code, sender_state.transmitter.submit(frame))
+        .map_err(|x| Custom(Status::InternalServerError, x.to_string()))?;
+    Ok("Success".to_string())
 }
 
 #[get("/")]
-fn get_devices(sender_state: State<SenderState>) -> Json<Vec<repo::Device>> {
-    Json(sender_state.repo.get_devices().unwrap())
+fn get_devices(
+    sender_state: State<SenderState>,
+) -> Result<Json<Vec<repo::Device>>, Custom<String>> {
+    match sender_state.repo.get_devices() {
+        Ok(devices) => Ok(Json(devices)),
+        Err(x) => Err(Custom(error_status(&x), x.to_string())),
+    }
+}
+
+/// Frames sent by the simulated radio, for checking what the api would have
+/// transmitted.
+#[get("/simulated")]
+fn get_simulated_frames(sender_state: State<SenderState>) -> Option<Json<Vec<String>>> {
+    sender_state
+        .simulated
+        .as_ref()
+        .map(|radio| Json(radio.frames()))
 }
 
 fn periodic_state_publish(sender: SenderState) {
@@ -210,13 +757,13 @@ fn periodic_state_publish(sender: SenderState) {
         if let Ok(devices) = sender.repo.get_devices() {
             for device in devices.iter() {
                 let device_name = device.id.to_string();
-                if let Some((sender, device_number)) =
-                    get_device_number_from_id(&device_name, &sender)
-                {
-                    match device.current_state {
+                if let Some((sender, device_number)) = get_nexa_device(device.id, &sender) {
+                    let transmission = match device.current_state {
                         true => sender.turn_device_on(device_number),
                         false => sender.turn_device_off(device_number),
-                    }
+                    };
+                    // failures are logged, the next round tries again
+                    let _ = wait_for_transmission(&device_name, transmission);
                 }
             }
         }
@@ -225,24 +772,76 @@ fn periodic_state_publish(sender: SenderState) {
     }
 }
 
-fn main() {
-    const GPIO_LED: u8 = 17;
-    let pin = Arc::new(Mutex::new(
-        Gpio::new().unwrap().get(GPIO_LED).unwrap().into_output(),
-    ));
+fn receive_remote_commands(receiver: Receiver, sender_state: SenderState) {
+    let mut last: Option<(nexa::Command, Instant)> = None;
+    while let Some(frame) = receiver.recv_frame() {
+        let command = match nexa::decode(&frame) {
+            Some(command) => command,
+            None => continue,
+        };
+        // remotes send every press several times
+        if let Some((previous, received_at)) = last {
+            if previous == command && received_at.elapsed() < REPEAT_WINDOW {
+                continue;
+            }
+        }
+        last = Some((command, Instant::now()));
+        info!("Received {:?}", command);
+        sync_device_state(&command, &sender_state);
+    }
+    warn!("Receiver stopped");
+}
 
-    let repo = repo::Repo::new("/home/pi/test.db");
-    repo.assure_created().unwrap();
+fn sync_device_state(command: &nexa::Command, sender_state: &SenderState) {
+    let devices = match sender_state.repo.get_devices() {
+        Ok(devices) => devices,
+        Err(err) => {
+            error!("Could not read devices ({})", err);
+            return;
+        }
+    };
+    for mut device in devices {
+        let device_name = device.id.to_string();
+        if let Some((sender, device_number)) = get_nexa_device(device.id, sender_state) {
+            if sender.sender_id() == command.sender_id
+                && (command.whole_group || device_number == command.device)
+            {
+                device.current_state = command.mode != nexa::DeviceMode::Off;
+                if let Err(err) = sender_state.repo.update_device(&device) {
+                    error!("Failed to update {} ({})", device_name, err);
+                }
+            }
+        }
+    }
+}
 
-    let nexa_state = SenderState {
-        sender_one: nexa::Nexa::new("11000000000000000000000010", Arc::clone(&pin)), //50331650
-        sender_two: nexa::Nexa::new("11000000000000000000000001", Arc::clone(&pin)), //50331649
-        sender_three: nexa::Nexa::new("11000000000000000000000000", Arc::clone(&pin)), // 50331648
-        sender_four: nexa::Nexa::new("11000000000000000000000011", Arc::clone(&pin)), // 50331651
-        sender_five: nexa::Nexa::new("11000000000000000000000100", Arc::clone(&pin)), // 50331651
-        rollo: rollo::Rollo::new("FQ1Q011000Q00F000", Arc::clone(&pin)),
-        repo,
+// A receiver that cannot be opened only costs the state sync, the api works
+// without it.
+fn open_receiver(bcm_pin: u8) -> Option<Receiver> {
+    let pin = match Gpio::new().and_then(|gpio| gpio.get(bcm_pin)) {
+        Ok(pin) => pin.into_input(),
+        Err(err) => {
+            error!("Could not open receiver pin {} ({})", bcm_pin, err);
+            return None;
+        }
     };
+    match Receiver::new(pin) {
+        Ok(receiver) => Some(receiver),
+        Err(err) => {
+            error!("Could not start receiver ({})", err);
+            None
+        }
+    }
+}
+
+fn main() {
+    const GPIO_LED: u8 = 17;
+    const GPIO_RECEIVER: u8 = 27;
+    // --simulate runs without a Pi, logging frames instead of sending them
+    let simulate = env::args().any(|arg| arg == "--simulate");
+    // --receiver listens for remotes, only to be given with a receiver wired
+    // up as a floating input reads a flood of noise
+    let listen = env::args().any(|arg| arg == "--receiver");
 
     let logfile = FileAppender::builder()
         .encoder(Box::new(PatternEncoder::new("{d} - {m}{n}")))
@@ -260,19 +859,381 @@ fn main() {
 
     log4rs::init_config(log_config).unwrap();
 
+    let (transmitter, receiver, simulated) = if simulate {
+        let radio = simulated::SimulatedRadio::new();
+        (Transmitter::spawn(radio.clone()), None, Some(radio))
+    } else {
+        let receiver = match listen {
+            true => open_receiver(GPIO_RECEIVER),
+            false => None,
+        };
+        (Transmitter::open(GPIO_LED).unwrap(), receiver, None)
+    };
+
+    let repo = match simulate {
+        true => repo::Repo::new("simulated.db"),
+        false => repo::Repo::new("/home/pi/test.db"),
+    };
+    repo.assure_created().unwrap();
+    repo.ensure_updated().unwrap();
+
+    let devices = dispatch::Dispatch::load(repo.clone(), transmitter.clone()).unwrap();
+
+    let nexa_state = SenderState {
+        devices,
+        transmitter,
+        simulated,
+        repo,
+    };
+
+    if let Some(receiver) = receiver {
+        let receiver_state = nexa_state.clone();
+        thread::spawn(move || receive_remote_commands(receiver, receiver_state));
+    }
+
     // let state2 = nexa_state.clone();
     // thread::spawn(move || periodic_state_publish(state2));
 
     let config = Config::build(Environment::Production)
         .address("0.0.0.0")
-        .port(80)
+        // binding port 80 needs root, which a development machine should not
+        .port(if simulate { 8000 } else { 80 })
         .finalize()
         .unwrap();
 
-    rocket::custom(config)
-        .manage(nexa_state)
-        .mount("/api/set", routes![set_device, post_device])
-        .mount("/api/", routes![get_devices])
+    rocket(config, nexa_state)
         .mount("/", StaticFiles::from("/home/pi/home-automation/"))
         .launch();
 }
+
+fn rocket(config: Config, sender_state: SenderState) -> rocket::Rocket {
+    rocket::custom(config)
+        .manage(sender_state)
+        .mount("/api/set", routes![set_device, post_device])
+        .mount(
+            "/api/",
+            routes![get_devices, post_raw, get_simulated_frames],
+        )
+        .mount(
+            "/api/devices",
+            routes![
+                get_device,
+                create_device,
+                update_device,
+                delete_device,
+                pair_device,
+                unpair_device
+            ],
+        )
+        .mount(
+            "/api/groups",
+            routes![
+                get_groups,
+                create_group,
+                update_group,
+                delete_group,
+                switch_group
+            ],
+        )
+}
+
+#[cfg(test)]
+use rocket::http::ContentType;
+#[cfg(test)]
+use rocket::local::Client;
+
+#[test]
+fn test_create_device() {
+    let repo = repo::Repo::new("test_create_device_route.db");
+    repo.assure_created().unwrap();
+    repo.ensure_updated().unwrap();
+    let radio = simulated::SimulatedRadio::new();
+    let transmitter = Transmitter::spawn(radio.clone());
+    let state = SenderState {
+        devices: dispatch::Dispatch::load(repo.clone(), transmitter.clone()).unwrap(),
+        transmitter,
+        simulated: Some(radio),
+        repo: repo.clone(),
+    };
+    let client = Client::new(rocket(Config::development(), state)).unwrap();
+
+    // a device does not have to be in a group
+    let response = client
+        .post("/api/devices")
+        .header(ContentType::JSON)
+        .body(r#"{"name": " lamp "}"#)
+        .dispatch();
+    assert!(response.status() == Status::Created);
+    let id = repo.get_device_id_by_name("lamp").unwrap().unwrap();
+    assert!(response.headers().get_one("Location") == Some(&*format!("/api/devices/{}", id)));
+    assert!(repo.get_device(id).unwrap().unwrap().group_id == repo::NO_GROUP);
+
+    let response = client
+        .post("/api/devices")
+        .header(ContentType::JSON)
+        .body(r#"{"name": "lamp"}"#)
+        .dispatch();
+    assert!(response.status() == Status::Conflict);
+    let response = client
+        .post("/api/devices")
+        .header(ContentType::JSON)
+        .body(r#"{"name": "heater", "group_id": 99}"#)
+        .dispatch();
+    assert!(response.status() == Status::BadRequest);
+    let response = client
+        .post("/api/devices")
+        .header(ContentType::JSON)
+        .body(r#"{"name": " "}"#)
+        .dispatch();
+    assert!(response.status() == Status::BadRequest);
+    assert!(repo.get_device_id_by_name("heater").unwrap().is_none());
+    std::fs::remove_file("test_create_device_route.db").unwrap();
+}
+
+#[test]
+fn test_update_device() {
+    let repo = repo::Repo::new("test_update_device_route.db");
+    repo.assure_created().unwrap();
+    repo.ensure_updated().unwrap();
+    let radio = simulated::SimulatedRadio::new();
+    let transmitter = Transmitter::spawn(radio.clone());
+    let state = SenderState {
+        devices: dispatch::Dispatch::load(repo.clone(), transmitter.clone()).unwrap(),
+        transmitter,
+        simulated: Some(radio),
+        repo: repo.clone(),
+    };
+    let client = Client::new(rocket(Config::development(), state)).unwrap();
+    let mut kitchen = repo::Group {
+        id: 0,
+        name: "Kitchen".to_string(),
+        icon: String::new(),
+        sort_order: 0,
+    };
+    repo.add_group(&mut kitchen).unwrap();
+    let mut lamp = repo::Device::new("lamp", repo::NO_GROUP, false);
+    let mut heater = repo::Device::new("heater", repo::NO_GROUP, false);
+    repo.add_device(&mut lamp).unwrap();
+    repo.add_device(&mut heater).unwrap();
+
+    let response = client
+        .put(format!("/api/devices/{}", lamp.id))
+        .header(ContentType::JSON)
+        .body(format!(
+            r#"{{"name": "floor lamp", "group_id": {}}}"#,
+            kitchen.id
+        ))
+        .dispatch();
+    assert!(response.status() == Status::Ok);
+    let updated = repo.get_device(lamp.id).unwrap().unwrap();
+    assert!(updated.name == "floor lamp" && updated.group_id == kitchen.id);
+
+    let response = client
+        .put(format!("/api/devices/{}", heater.id))
+        .header(ContentType::JSON)
+        .body(r#"{"name": "floor lamp"}"#)
+        .dispatch();
+    assert!(response.status() == Status::Conflict);
+    let response = client
+        .put(format!("/api/devices/{}", heater.id))
+        .header(ContentType::JSON)
+        .body(r#"{"name": "heater", "group_id": 99}"#)
+        .dispatch();
+    assert!(response.status() == Status::BadRequest);
+    let response = client
+        .put("/api/devices/99")
+        .header(ContentType::JSON)
+        .body(r#"{"name": "fan"}"#)
+        .dispatch();
+    assert!(response.status() == Status::NotFound);
+    assert!(repo.get_device(heater.id).unwrap().unwrap().name == "heater");
+    std::fs::remove_file("test_update_device_route.db").unwrap();
+}
+
+#[test]
+fn test_delete_device() {
+    let repo = repo::Repo::new("test_delete_device_route.db");
+    repo.assure_created().unwrap();
+    repo.ensure_updated().unwrap();
+    let radio = simulated::SimulatedRadio::new();
+    let transmitter = Transmitter::spawn(radio.clone());
+    let state = SenderState {
+        devices: dispatch::Dispatch::load(repo.clone(), transmitter.clone()).unwrap(),
+        transmitter,
+        simulated: Some(radio),
+        repo: repo.clone(),
+    };
+    let devices = state.devices.clone();
+    let client = Client::new(rocket(Config::development(), state)).unwrap();
+    let mut lamp = repo::Device::new("lamp", repo::NO_GROUP, false);
+    repo.add_device(&mut lamp).unwrap();
+    repo.set_nexa_transport(&repo::NexaTransport {
+        device_id: lamp.id,
+        sender_id: 12345678,
+        unit: 1,
+    })
+    .unwrap();
+    devices.reload().unwrap();
+
+    let response = client
+        .delete(format!("/api/devices/{}", lamp.id))
+        .dispatch();
+    assert!(response.status() == Status::NoContent);
+    assert!(repo.get_device(lamp.id).unwrap().is_none());
+    assert!(devices.get(lamp.id).is_none());
+    let response = client
+        .delete(format!("/api/devices/{}", lamp.id))
+        .dispatch();
+    assert!(response.status() == Status::NotFound);
+    std::fs::remove_file("test_delete_device_route.db").unwrap();
+}
+
+#[test]
+fn test_create_group() {
+    let repo = repo::Repo::new("test_create_group_route.db");
+    repo.assure_created().unwrap();
+    repo.ensure_updated().unwrap();
+    let radio = simulated::SimulatedRadio::new();
+    let transmitter = Transmitter::spawn(radio.clone());
+    let state = SenderState {
+        devices: dispatch::Dispatch::load(repo.clone(), transmitter.clone()).unwrap(),
+        transmitter,
+        simulated: Some(radio),
+        repo: repo.clone(),
+    };
+    let client = Client::new(rocket(Config::development(), state)).unwrap();
+    let before = repo.get_groups().unwrap().len();
+
+    let response = client
+        .post("/api/groups")
+        .header(ContentType::JSON)
+        .body(r#"{"name": " Kitchen ", "icon": "kitchen"}"#)
+        .dispatch();
+    assert!(response.status() == Status::Created);
+    let groups = repo.get_groups().unwrap();
+    let kitchen = groups.iter().find(|g| g.name == "Kitchen").unwrap();
+    assert!(kitchen.icon == "kitchen");
+    assert!(
+        response.headers().get_one("Location") == Some(&*format!("/api/groups/{}", kitchen.id))
+    );
+
+    let response = client
+        .post("/api/groups")
+        .header(ContentType::JSON)
+        .body(r#"{"name": "Kitchen"}"#)
+        .dispatch();
+    assert!(response.status() == Status::Conflict);
+    let response = client
+        .post("/api/groups")
+        .header(ContentType::JSON)
+        .body(r#"{"name": ""}"#)
+        .dispatch();
+    assert!(response.status() == Status::BadRequest);
+    assert!(repo.get_groups().unwrap().len() == before + 1);
+    std::fs::remove_file("test_create_group_route.db").unwrap();
+}
+
+#[test]
+fn test_switch_group() {
+    let repo = repo::Repo::new("test_switch_group_route.db");
+    repo.assure_created().unwrap();
+    repo.ensure_updated().unwrap();
+    let radio = simulated::SimulatedRadio::new();
+    let transmitter = Transmitter::spawn(radio.clone());
+    let state = SenderState {
+        devices: dispatch::Dispatch::load(repo.clone(), transmitter.clone()).unwrap(),
+        transmitter,
+        simulated: Some(radio.clone()),
+        repo: repo.clone(),
+    };
+    let devices = state.devices.clone();
+    let client = Client::new(rocket(Config::development(), state)).unwrap();
+    let mut kitchen = repo::Group {
+        id: 0,
+        name: "Kitchen".to_string(),
+        icon: String::new(),
+        sort_order: 0,
+    };
+    repo.add_group(&mut kitchen).unwrap();
+    let mut lamp = repo::Device::new("lamp", kitchen.id, false);
+    repo.add_device(&mut lamp).unwrap();
+    repo.set_nexa_transport(&repo::NexaTransport {
+        device_id: lamp.id,
+        sender_id: 12345678,
+        unit: 1,
+    })
+    .unwrap();
+    devices.reload().unwrap();
+
+    let response = client
+        .post(format!("/api/groups/{}?mode=on", kitchen.id))
+        .dispatch();
+    assert!(response.status() == Status::Ok);
+    assert!(repo.get_device(lamp.id).unwrap().unwrap().current_state);
+    assert!(radio.frames().len() == 1);
+
+    let response = client
+        .post(format!("/api/groups/{}?mode=dim", kitchen.id))
+        .dispatch();
+    assert!(response.status() == Status::BadRequest);
+    let response = client.post("/api/groups/99?mode=off").dispatch();
+    assert!(response.status() == Status::NotFound);
+    assert!(radio.frames().len() == 1);
+    std::fs::remove_file("test_switch_group_route.db").unwrap();
+}
+
+#[test]
+fn test_set_remote_group() {
+    let repo = repo::Repo::new("test_set_remote_group.db");
+    repo.assure_created().unwrap();
+    repo.ensure_updated().unwrap();
+    let radio = simulated::SimulatedRadio::new();
+    let transmitter = Transmitter::spawn(radio.clone());
+    let state = SenderState {
+        devices: dispatch::Dispatch::load(repo.clone(), transmitter.clone()).unwrap(),
+        transmitter,
+        simulated: Some(radio.clone()),
+        repo,
+    };
+    let client = Client::new(rocket(Config::development(), state)).unwrap();
+
+    // m3 is the third remote, switched on and not off
+    let response = client.get("/api/set/m3?mode=on").dispatch();
+    assert!(response.status() == Status::Ok);
+    let frames = radio.frames();
+    assert!(frames.len() == 1);
+    assert!(frames[0].contains("50331648") && frames[0].contains("On"));
+
+    let response = client.get("/api/set/all?mode=off").dispatch();
+    assert!(response.status() == Status::Ok);
+    assert!(radio.frames().len() == 4);
+    let response = client.get("/api/set/m1?mode=dim").dispatch();
+    assert!(response.status() == Status::BadRequest);
+    let response = client.get("/api/set/m4?mode=on").dispatch();
+    assert!(response.status() == Status::NotFound);
+    assert!(radio.frames().len() == 4);
+    std::fs::remove_file("test_set_remote_group.db").unwrap();
+}
+
+#[test]
+fn test_set_blind_by_alias() {
+    let repo = repo::Repo::new("test_set_blind_by_alias.db");
+    repo.assure_created().unwrap();
+    repo.ensure_updated().unwrap();
+    let radio = simulated::SimulatedRadio::new();
+    let transmitter = Transmitter::spawn(radio.clone());
+    let state = SenderState {
+        devices: dispatch::Dispatch::load(repo.clone(), transmitter.clone()).unwrap(),
+        transmitter,
+        simulated: Some(radio.clone()),
+        repo: repo.clone(),
+    };
+    let client = Client::new(rocket(Config::development(), state)).unwrap();
+
+    // the app still switches the blind of a fresh install as "r"
+    let response = client.get("/api/set/r?mode=down").dispatch();
+    assert!(response.status() == Status::Ok);
+    assert!(radio.frames().last().unwrap() == "rollo FQ1Q011000Q00F000 DOWN");
+    assert!(repo.get_blind_position("FQ1Q011000Q00F000").unwrap() == Some(100));
+    std::fs::remove_file("test_set_blind_by_alias.db").unwrap();
+}
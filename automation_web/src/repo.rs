@@ -1,22 +1,170 @@
 use rusqlite::{params, Connection, Error, Result};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Serialize)]
+/// Group id of devices that are in no group.
+pub const NO_GROUP: i64 = 0;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Device {
+    #[serde(default)]
     pub id: i64,
     pub name: String,
-    pub group_id: i32,
+    /// NO_GROUP until the device is put in a group.
+    #[serde(default)]
+    pub group_id: i64,
+    #[serde(default)]
     pub current_state: bool,
+    #[serde(default)]
     pub references: String,
 }
 
+/// The Nexa sender id and unit a device was paired with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct NexaTransport {
+    pub device_id: i64,
+    pub sender_id: u32,
+    pub unit: u8,
+}
+
+// Schema changes made after the tables created by assure_created. A database
+// at user_version n has had the first n applied, so migrations are only ever
+// appended to the end.
+const MIGRATIONS: [&str; 10] = [
+    "CREATE TABLE IF NOT EXISTS blind_positions (
+        code VARCHAR(17) PRIMARY KEY,
+        position INTEGER NOT NULL
+    );",
+    "CREATE TABLE IF NOT EXISTS nexa_devices (
+        device_id INTEGER PRIMARY KEY REFERENCES devices(id),
+        sender_id INTEGER NOT NULL,
+        unit INTEGER NOT NULL
+    );",
+    // databases created before assure_created ran its whole batch lack
+    // device_ref_device
+    "CREATE TABLE IF NOT EXISTS device_ref_device (
+        id integer primary key AUTOINCREMENT,
+        device_id integer REFERENCES devices(id) not null,
+        reference_device_id integer references devices(id) not null
+    );
+    CREATE INDEX IF NOT EXISTS device_ref_device_device_id ON device_ref_device(device_id);",
+    "CREATE TABLE IF NOT EXISTS rollo_devices (
+        device_id INTEGER PRIMARY KEY REFERENCES devices(id),
+        code VARCHAR(17) NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS relay_devices (
+        device_id INTEGER PRIMARY KEY REFERENCES devices(id),
+        base_url VARCHAR(200) NOT NULL,
+        relay_id VARCHAR(20) NOT NULL
+    );",
+    // the devices that used to be hard coded, wherever they exist
+    "INSERT OR IGNORE INTO nexa_devices(device_id, sender_id, unit)
+    SELECT column1, column2, column3 FROM (VALUES
+        (1, 50331650, 1), (2, 50331650, 2), (3, 50331650, 3),
+        (4, 50331649, 1), (5, 50331649, 2), (6, 50331649, 3),
+        (7, 50331648, 1), (8, 50331648, 2), (9, 50331648, 3),
+        (10, 50331651, 1),
+        (13, 50331652, 1), (14, 50331652, 2), (15, 50331652, 3))
+    WHERE column1 IN (SELECT id FROM devices);
+    INSERT OR IGNORE INTO relay_devices(device_id, base_url, relay_id)
+    SELECT column1, column2, column3 FROM (VALUES
+        (11, 'http://192.168.10.124', '4'),
+        (12, 'http://192.168.10.124', '5'))
+    WHERE column1 IN (SELECT id FROM devices);",
+    // every group_id in use becomes a group, to be named in the app
+    "CREATE TABLE IF NOT EXISTS groups (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name VARCHAR(100) NOT NULL,
+        icon VARCHAR(100) NOT NULL DEFAULT '',
+        sort_order INTEGER NOT NULL DEFAULT 0
+    );
+    INSERT OR IGNORE INTO groups(id, name, sort_order)
+    SELECT DISTINCT group_id, 'Group ' || group_id, group_id FROM devices;",
+    // the blind that used to be hard coded as "r", unless it already is a
+    // device
+    "INSERT INTO groups(name, sort_order)
+    SELECT 'Blinds', (SELECT COALESCE(MAX(sort_order), 0) + 1 FROM groups)
+    WHERE NOT EXISTS (SELECT 1 FROM rollo_devices WHERE code = 'FQ1Q011000Q00F000');
+    INSERT INTO devices(name, group_id)
+    SELECT 'Blind', (SELECT MAX(id) FROM groups)
+    WHERE NOT EXISTS (SELECT 1 FROM rollo_devices WHERE code = 'FQ1Q011000Q00F000');
+    INSERT INTO rollo_devices(device_id, code)
+    SELECT (SELECT MAX(id) FROM devices), 'FQ1Q011000Q00F000'
+    WHERE NOT EXISTS (SELECT 1 FROM rollo_devices WHERE code = 'FQ1Q011000Q00F000');",
+    // the senders of the remotes, and those pair_device made up for devices
+    // that had none, which are the only ones unpairing may forget
+    "CREATE TABLE IF NOT EXISTS nexa_senders (
+        sender_id INTEGER PRIMARY KEY,
+        generated BIT NOT NULL DEFAULT 0
+    );
+    INSERT OR IGNORE INTO nexa_senders(sender_id)
+    VALUES (50331648), (50331649), (50331650), (50331651), (50331652);
+    INSERT OR IGNORE INTO nexa_senders(sender_id, generated)
+    SELECT DISTINCT sender_id, 1 FROM nexa_devices;",
+    // the groups of the remotes that used to be hard coded as m1 to m3
+    "CREATE TABLE IF NOT EXISTS nexa_groups (
+        name VARCHAR(20) PRIMARY KEY,
+        sender_id INTEGER NOT NULL REFERENCES nexa_senders(sender_id)
+    );
+    INSERT OR IGNORE INTO nexa_groups(name, sender_id)
+    VALUES ('m1', 50331650), ('m2', 50331649), ('m3', 50331648);",
+    // names the app used for devices before they had ids, such as "r" for
+    // the blind
+    "CREATE TABLE IF NOT EXISTS device_aliases (
+        name VARCHAR(20) PRIMARY KEY,
+        device_id INTEGER NOT NULL REFERENCES devices(id)
+    );
+    INSERT OR IGNORE INTO device_aliases(name, device_id)
+    SELECT 'r', device_id FROM rollo_devices WHERE code = 'FQ1Q011000Q00F000';",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Group {
+    #[serde(default)]
+    pub id: i64,
+    pub name: String,
+    #[serde(default)]
+    pub icon: String,
+    #[serde(default)]
+    pub sort_order: i32,
+}
+
+/// A blind, or a channel of the remote moving the blinds the device
+/// references.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RolloTransport {
+    pub device_id: i64,
+    pub code: String,
+}
+
+/// A group of the receivers paired with a sender, all switched at once.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NexaGroup {
+    pub name: String,
+    pub sender_id: u32,
+}
+
+/// Another name a device can be switched by.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceAlias {
+    pub name: String,
+    pub device_id: i64,
+}
+
+/// A relay switched by calling base_url/relay_id/mode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RelayTransport {
+    pub device_id: i64,
+    pub base_url: String,
+    pub relay_id: String,
+}
+
 #[derive(Default, Clone)]
 pub struct Repo {
     connection_string: String,
 }
 
 impl Device {
-    pub fn new(name: &str, group_id: i32, current_state: bool) -> Device {
+    pub fn new(name: &str, group_id: i64, current_state: bool) -> Device {
         Device {
             id: 0,
             name: String::from(name),
@@ -35,22 +183,22 @@ impl Repo {
     }
 
     pub fn assure_created(&self) -> Result<bool> {
-        let conn = Connection::open(&self.connection_string)?;
+        let mut conn = Connection::open(&self.connection_string)?;
 
-        let mut statement =
-            conn.prepare("SELECT name FROM sqlite_master WHERE type='table' AND name='devices'")?;
-        match statement.exists([]) {
-            Ok(x) => {
-                if x {
-                    return Ok(true);
-                }
-            }
-            Err(err) => return Err(err),
+        let exists = conn
+            .prepare("SELECT name FROM sqlite_master WHERE type='table' AND name='devices'")?
+            .exists([])?;
+        if exists {
+            return Ok(true);
         }
 
         println!("Table not existing, creating");
 
-        match conn.execute(
+        // execute only runs the first statement of the string, the batch runs
+        // them all and the transaction keeps a failure from leaving half a
+        // schema behind
+        let transaction = conn.transaction()?;
+        transaction.execute_batch(
             "
     		CREATE TABLE devices (
 				id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -64,17 +212,27 @@ impl Repo {
 				reference_device_id integer references devices(id) not null
 			);
     		",
-            params![],
-        ) {
-            Ok(_) => Ok(true),
-            Err(err) => Err(err),
-        }
+        )?;
+        transaction.commit()?;
+        Ok(true)
     }
 
+    /// Applies the migrations the database has not seen yet, each in its own
+    /// transaction together with the bump of user_version.
     pub fn ensure_updated(&self) -> Result<bool> {
-        let conn = Connection::open(&self.connection_string)?;
-        let statement = conn.prepare("PRAGMA user_version")?;
-        
+        let mut conn = Connection::open(&self.connection_string)?;
+        let version: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version as usize > MIGRATIONS.len() {
+            log::warn!("Database version {} is newer than this build", version);
+        }
+
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let transaction = conn.transaction()?;
+            transaction.execute_batch(migration)?;
+            transaction.pragma_update(None, "user_version", index as u32 + 1)?;
+            transaction.commit()?;
+            log::info!("Migrated database to version {}", index + 1);
+        }
         Ok(true)
     }
 
@@ -131,7 +289,7 @@ from device_ref_device group by device_id) as reftable on d.id = reftable.device
         };
     }
 
-    pub fn get_group(&self, group_id: i32) -> Result<Vec<Device>> {
+    pub fn get_group(&self, group_id: i64) -> Result<Vec<Device>> {
         let conn = Connection::open(&self.connection_string)?;
 
         let mut statement = conn
@@ -174,6 +332,59 @@ from device_ref_device group by device_id) as reftable on d.id = reftable.device
         }
     }
 
+    /// Renames the device and moves it to its group. False when there is no
+    /// such device.
+    pub fn update_device_details(&self, device: &Device) -> Result<bool> {
+        let conn = Connection::open(&self.connection_string)?;
+        let mut statement =
+            conn.prepare("UPDATE devices SET name = ?1, group_id = ?2 WHERE id = ?3")?;
+
+        match statement.execute(params![device.name, device.group_id, device.id]) {
+            Ok(updated) => Ok(updated > 0),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Deletes the device together with its references and transport. False
+    /// when there is no such device.
+    pub fn delete_device(&self, id: i64) -> Result<bool> {
+        let mut conn = Connection::open(&self.connection_string)?;
+        let transaction = conn.transaction()?;
+
+        transaction.execute(
+            "DELETE FROM device_ref_device WHERE device_id = ?1 OR reference_device_id = ?1",
+            params![id],
+        )?;
+        for table in [
+            "nexa_devices",
+            "rollo_devices",
+            "relay_devices",
+            "device_aliases",
+        ] {
+            transaction.execute(
+                &format!("DELETE FROM {} WHERE device_id = ?1", table),
+                params![id],
+            )?;
+        }
+        let deleted = transaction.execute("DELETE FROM devices WHERE id = ?1", params![id])?;
+        transaction.commit()?;
+        Ok(deleted > 0)
+    }
+
+    pub fn get_device_id_by_name(&self, name: &str) -> Result<Option<i64>> {
+        let conn = Connection::open(&self.connection_string)?;
+
+        match conn.query_row(
+            "SELECT id FROM devices WHERE name = ?1",
+            params![name],
+            |row| row.get(0),
+        ) {
+            Ok(id) => Ok(Some(id)),
+            Err(Error::QueryReturnedNoRows) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
     pub fn update_device(&self, device: &Device) -> Result<bool> {
         let conn = Connection::open(&self.connection_string)?;
         let mut statement =
@@ -198,38 +409,287 @@ from device_ref_device group by device_id) as reftable on d.id = reftable.device
         Ok(true)
     }
 
-    pub fn get_groups(&self) -> Result<Vec<i64>> {
+    pub fn get_blind_position(&self, code: &str) -> Result<Option<u8>> {
+        let conn = Connection::open(&self.connection_string)?;
+
+        match conn.query_row(
+            "SELECT position FROM blind_positions WHERE code = ?1",
+            params![code],
+            |row| row.get(0),
+        ) {
+            Ok(position) => Ok(Some(position)),
+            Err(Error::QueryReturnedNoRows) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn set_blind_position(&self, code: &str, position: u8) -> Result<bool> {
+        let conn = Connection::open(&self.connection_string)?;
+        let mut statement =
+            conn.prepare("INSERT OR REPLACE INTO blind_positions(code, position) VALUES(?1, ?2)")?;
+
+        match statement.execute(params![code, position]) {
+            Ok(_) => Ok(true),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn get_nexa_transport(&self, device_id: i64) -> Result<Option<NexaTransport>> {
+        let conn = Connection::open(&self.connection_string)?;
+
+        match conn.query_row(
+            "SELECT device_id, sender_id, unit FROM nexa_devices WHERE device_id = ?1",
+            params![device_id],
+            |row| {
+                Ok(NexaTransport {
+                    device_id: row.get(0)?,
+                    sender_id: row.get(1)?,
+                    unit: row.get(2)?,
+                })
+            },
+        ) {
+            Ok(transport) => Ok(Some(transport)),
+            Err(Error::QueryReturnedNoRows) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn set_nexa_transport(&self, transport: &NexaTransport) -> Result<bool> {
+        let conn = Connection::open(&self.connection_string)?;
+        let mut statement = conn.prepare(
+            "INSERT OR REPLACE INTO nexa_devices(device_id, sender_id, unit) VALUES(?1, ?2, ?3)",
+        )?;
+
+        match statement.execute(params![
+            transport.device_id,
+            transport.sender_id,
+            transport.unit
+        ]) {
+            Ok(_) => Ok(true),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn delete_nexa_transport(&self, device_id: i64) -> Result<bool> {
+        let conn = Connection::open(&self.connection_string)?;
+        let mut statement = conn.prepare("DELETE FROM nexa_devices WHERE device_id = ?1")?;
+
+        match statement.execute(params![device_id]) {
+            Ok(deleted) => Ok(deleted > 0),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn get_nexa_transports(&self) -> Result<Vec<NexaTransport>> {
         let conn = Connection::open(&self.connection_string)?;
-        let mut statement = conn.prepare("SELECT group_id FROM devices")?;
+        let mut statement = conn.prepare("SELECT device_id, sender_id, unit FROM nexa_devices")?;
+
+        let transports = statement.query_map([], |row| {
+            Ok(NexaTransport {
+                device_id: row.get(0)?,
+                sender_id: row.get(1)?,
+                unit: row.get(2)?,
+            })
+        })?;
 
-        let mut result: Vec<i64> = vec![];
+        transports.collect()
+    }
 
-        let group_iter: Iter<Result<i32>> = statement.query_row([], |&row | {
-            Ok(row.get(0)?)
+    pub fn get_rollo_transports(&self) -> Result<Vec<RolloTransport>> {
+        let conn = Connection::open(&self.connection_string)?;
+        let mut statement = conn.prepare("SELECT device_id, code FROM rollo_devices")?;
+
+        let transports = statement.query_map([], |row| {
+            Ok(RolloTransport {
+                device_id: row.get(0)?,
+                code: row.get(1)?,
+            })
         })?;
-        
-        group_iter.for_each(|id| {
-            result.append(id.unwrap());
-        });
-        Ok(result)
+
+        transports.collect()
+    }
+
+    /// Ids of the devices a device references.
+    pub fn get_references(&self, device_id: i64) -> Result<Vec<i64>> {
+        let conn = Connection::open(&self.connection_string)?;
+        let mut statement = conn.prepare(
+            "SELECT reference_device_id FROM device_ref_device WHERE device_id = ?1 ORDER BY id",
+        )?;
+
+        let references = statement.query_map(params![device_id], |row| row.get(0))?;
+
+        references.collect()
+    }
+
+    pub fn get_relay_transports(&self) -> Result<Vec<RelayTransport>> {
+        let conn = Connection::open(&self.connection_string)?;
+        let mut statement =
+            conn.prepare("SELECT device_id, base_url, relay_id FROM relay_devices")?;
+
+        let transports = statement.query_map([], |row| {
+            Ok(RelayTransport {
+                device_id: row.get(0)?,
+                base_url: row.get(1)?,
+                relay_id: row.get(2)?,
+            })
+        })?;
+
+        transports.collect()
+    }
+
+    pub fn get_device_aliases(&self) -> Result<Vec<DeviceAlias>> {
+        let conn = Connection::open(&self.connection_string)?;
+        let mut statement = conn.prepare("SELECT name, device_id FROM device_aliases")?;
+
+        let aliases = statement.query_map([], |row| {
+            Ok(DeviceAlias {
+                name: row.get(0)?,
+                device_id: row.get(1)?,
+            })
+        })?;
+
+        aliases.collect()
+    }
+
+    pub fn get_nexa_groups(&self) -> Result<Vec<NexaGroup>> {
+        let conn = Connection::open(&self.connection_string)?;
+        let mut statement =
+            conn.prepare("SELECT name, sender_id FROM nexa_groups ORDER BY name")?;
+
+        let groups = statement.query_map([], |row| {
+            Ok(NexaGroup {
+                name: row.get(0)?,
+                sender_id: row.get(1)?,
+            })
+        })?;
+
+        groups.collect()
+    }
+
+    /// Sender ids of the remotes and those handed out to devices so far.
+    pub fn get_nexa_sender_ids(&self) -> Result<Vec<u32>> {
+        let conn = Connection::open(&self.connection_string)?;
+        let mut statement = conn.prepare(
+            "SELECT sender_id FROM nexa_senders UNION SELECT sender_id FROM nexa_devices",
+        )?;
+
+        let sender_ids = statement.query_map([], |row| row.get(0))?;
+
+        sender_ids.collect()
+    }
+
+    /// Records a sender made up for pairing, as opposed to one of a remote.
+    pub fn add_generated_nexa_sender(&self, sender_id: u32) -> Result<bool> {
+        let conn = Connection::open(&self.connection_string)?;
+        let mut statement =
+            conn.prepare("INSERT OR IGNORE INTO nexa_senders(sender_id, generated) VALUES(?1, 1)")?;
+
+        match statement.execute(params![sender_id]) {
+            Ok(inserted) => Ok(inserted > 0),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn is_generated_nexa_sender(&self, sender_id: u32) -> Result<bool> {
+        let conn = Connection::open(&self.connection_string)?;
+
+        match conn.query_row(
+            "SELECT generated FROM nexa_senders WHERE sender_id = ?1",
+            params![sender_id],
+            |row| row.get(0),
+        ) {
+            Ok(generated) => Ok(generated),
+            Err(Error::QueryReturnedNoRows) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn get_groups(&self) -> Result<Vec<Group>> {
+        let conn = Connection::open(&self.connection_string)?;
+        let mut statement =
+            conn.prepare("SELECT id, name, icon, sort_order FROM groups ORDER BY sort_order, id")?;
+
+        let groups = statement.query_map([], |row| {
+            Ok(Group {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                icon: row.get(2)?,
+                sort_order: row.get(3)?,
+            })
+        })?;
+
+        groups.collect()
+    }
+
+    pub fn get_group_details(&self, id: i64) -> Result<Option<Group>> {
+        let conn = Connection::open(&self.connection_string)?;
+
+        match conn.query_row(
+            "SELECT id, name, icon, sort_order FROM groups WHERE id = ?1",
+            params![id],
+            |row| {
+                Ok(Group {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    icon: row.get(2)?,
+                    sort_order: row.get(3)?,
+                })
+            },
+        ) {
+            Ok(group) => Ok(Some(group)),
+            Err(Error::QueryReturnedNoRows) => Ok(None),
+            Err(err) => Err(err),
         }
+    }
+
+    pub fn add_group(&self, group: &mut Group) -> Result<bool> {
+        let conn = Connection::open(&self.connection_string)?;
+        let mut statement =
+            conn.prepare("INSERT INTO groups(name, icon, sort_order) VALUES(?1, ?2, ?3)")?;
+
+        group.id = statement.insert(params![group.name, group.icon, group.sort_order])?;
+        Ok(true)
+    }
+
+    /// False when there is no such group.
+    pub fn update_group(&self, group: &Group) -> Result<bool> {
+        let conn = Connection::open(&self.connection_string)?;
+        let mut statement =
+            conn.prepare("UPDATE groups SET name = ?1, icon = ?2, sort_order = ?3 WHERE id = ?4")?;
+
+        match statement.execute(params![group.name, group.icon, group.sort_order, group.id]) {
+            Ok(updated) => Ok(updated > 0),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// False when there is no such group. Devices in it are left as they are.
+    pub fn delete_group(&self, id: i64) -> Result<bool> {
+        let conn = Connection::open(&self.connection_string)?;
+        let mut statement = conn.prepare("DELETE FROM groups WHERE id = ?1")?;
+
+        match statement.execute(params![id]) {
+            Ok(deleted) => Ok(deleted > 0),
+            Err(err) => Err(err),
+        }
+    }
 }
 
 #[test]
 fn test_device_empty_database() {
-    let repo = Repo::new("test.db");
+    let repo = Repo::new("test_device_empty_database.db");
     repo.assure_created().unwrap();
     let device = repo.get_device(2);
 
     assert!(device.is_ok());
     assert!(device.unwrap().is_none());
 
-    std::fs::remove_file("test.db").unwrap();
+    std::fs::remove_file("test_device_empty_database.db").unwrap();
 }
 
 #[test]
 fn test_non_existing_database() {
-    let repo = Repo::new("test.db");
+    let repo = Repo::new("test_non_existing_database.db");
     let created = repo.assure_created();
     let created2 = repo.assure_created();
 
@@ -238,14 +698,14 @@ fn test_non_existing_database() {
     assert!(created.unwrap() == true);
     assert!(created2.unwrap() == true);
 
-    std::fs::remove_file("test.db").unwrap();
+    std::fs::remove_file("test_non_existing_database.db").unwrap();
 }
 
 #[test]
 fn test_insert_device() {
     let mut device = Device::new("test", 1, false);
 
-    let repo = Repo::new("test.db");
+    let repo = Repo::new("test_insert_device.db");
     repo.assure_created().unwrap();
 
     let inserted = repo.add_device(&mut device);
@@ -269,14 +729,14 @@ fn test_insert_device() {
 
     assert!(1 == group.len());
 
-    std::fs::remove_file("test.db").unwrap();
+    std::fs::remove_file("test_insert_device.db").unwrap();
 }
 
 #[test]
 fn test_update_device() {
     let mut device = Device::new("test", 1, false);
 
-    let repo = Repo::new("test.db");
+    let repo = Repo::new("test_update_device.db");
     repo.assure_created().unwrap();
 
     let inserted = repo.add_device(&mut device);
@@ -294,7 +754,63 @@ fn test_update_device() {
     let updated_device = updated.unwrap().unwrap();
 
     assert!(true == updated_device.current_state);
-    std::fs::remove_file("test.db").unwrap();
+    std::fs::remove_file("test_update_device.db").unwrap();
+}
+
+#[test]
+fn test_update_device_details() {
+    let repo = Repo::new("test_update_device_details.db");
+    repo.assure_created().unwrap();
+    let mut device = Device::new("lamp", 1, false);
+    repo.add_device(&mut device).unwrap();
+
+    device.name = "floor lamp".to_string();
+    device.group_id = 2;
+    assert!(repo.update_device_details(&device).unwrap());
+
+    let updated = repo.get_device(device.id).unwrap().unwrap();
+    assert!(updated.name == "floor lamp" && updated.group_id == 2);
+    assert!(repo.get_device_id_by_name("floor lamp").unwrap() == Some(device.id));
+    assert!(repo.get_device_id_by_name("lamp").unwrap().is_none());
+    device.id += 1;
+    assert!(!repo.update_device_details(&device).unwrap());
+    std::fs::remove_file("test_update_device_details.db").unwrap();
+}
+
+#[test]
+fn test_delete_device() {
+    let repo = Repo::new("test_delete_device.db");
+    repo.assure_created().unwrap();
+    repo.ensure_updated().unwrap();
+    let mut lamp = Device::new("lamp", 1, false);
+    let mut heater = Device::new("heater", 1, false);
+    repo.add_device(&mut lamp).unwrap();
+    repo.add_device(&mut heater).unwrap();
+    let conn = Connection::open("test_delete_device.db").unwrap();
+    conn.execute(
+        "INSERT INTO device_ref_device(device_id, reference_device_id) VALUES(?1, ?2)",
+        params![heater.id, lamp.id],
+    )
+    .unwrap();
+    repo.set_nexa_transport(&NexaTransport {
+        device_id: lamp.id,
+        sender_id: 12345678,
+        unit: 1,
+    })
+    .unwrap();
+
+    assert!(repo.delete_device(lamp.id).unwrap());
+
+    assert!(repo.get_device(lamp.id).unwrap().is_none());
+    assert!(repo.get_nexa_transport(lamp.id).unwrap().is_none());
+    assert!(repo
+        .get_device(heater.id)
+        .unwrap()
+        .unwrap()
+        .references
+        .is_empty());
+    assert!(!repo.delete_device(lamp.id).unwrap());
+    std::fs::remove_file("test_delete_device.db").unwrap();
 }
 
 #[test]
@@ -302,7 +818,7 @@ fn test_get_devices() {
     let mut device1 = Device::new("test1", 1, false);
     let mut device2 = Device::new("test2", 1, false);
 
-    let repo = Repo::new("test.db");
+    let repo = Repo::new("test_get_devices.db");
 
     repo.assure_created().unwrap();
 
@@ -312,6 +828,8 @@ fn test_get_devices() {
     let devices = repo.get_devices().unwrap();
 
     assert!(2 == devices.len());
+
+    std::fs::remove_file("test_get_devices.db").unwrap();
 }
 
 #[test]
@@ -319,7 +837,7 @@ fn test_get_devices_in_group() {
     let mut device1 = Device::new("test1", 1, false);
     let mut device2 = Device::new("test2", 2, false);
 
-    let repo = Repo::new("test.db");
+    let repo = Repo::new("test_get_devices_in_group.db");
 
     repo.assure_created().unwrap();
 
@@ -328,5 +846,244 @@ fn test_get_devices_in_group() {
 
     let group_devices = repo.get_group(1).unwrap();
 
-    assert!(1 == group_devices.len())
+    assert!(1 == group_devices.len());
+
+    std::fs::remove_file("test_get_devices_in_group.db").unwrap();
+}
+
+#[test]
+fn test_blind_position() {
+    let repo = Repo::new("test_blind_position.db");
+    repo.assure_created().unwrap();
+    repo.ensure_updated().unwrap();
+
+    assert!(repo
+        .get_blind_position("FQ1Q011000Q00F000")
+        .unwrap()
+        .is_none());
+
+    repo.set_blind_position("FQ1Q011000Q00F000", 40).unwrap();
+    repo.set_blind_position("FQ1Q011000Q00F000", 65).unwrap();
+
+    assert!(repo.get_blind_position("FQ1Q011000Q00F000").unwrap() == Some(65));
+    std::fs::remove_file("test_blind_position.db").unwrap();
+}
+
+#[test]
+fn test_nexa_transport() {
+    let repo = Repo::new("test_nexa_transport.db");
+    repo.assure_created().unwrap();
+    repo.ensure_updated().unwrap();
+    let mut device = Device::new("lamp", 1, false);
+    repo.add_device(&mut device).unwrap();
+    let transport = NexaTransport {
+        device_id: device.id,
+        sender_id: 12345678,
+        unit: 1,
+    };
+
+    assert!(repo.get_nexa_transport(device.id).unwrap().is_none());
+    repo.set_nexa_transport(&transport).unwrap();
+
+    assert!(repo.get_nexa_transport(device.id).unwrap() == Some(transport));
+    let sender_ids = repo.get_nexa_sender_ids().unwrap();
+    assert!(sender_ids.contains(&12345678) && sender_ids.contains(&50331650));
+    assert!(!repo.is_generated_nexa_sender(12345678).unwrap());
+    assert!(repo.add_generated_nexa_sender(12345678).unwrap());
+    assert!(repo.is_generated_nexa_sender(12345678).unwrap());
+    assert!(!repo.add_generated_nexa_sender(50331650).unwrap());
+    assert!(!repo.is_generated_nexa_sender(50331650).unwrap());
+    assert!(repo.delete_nexa_transport(device.id).unwrap());
+    assert!(!repo.delete_nexa_transport(device.id).unwrap());
+    assert!(repo.get_nexa_transport(device.id).unwrap().is_none());
+    std::fs::remove_file("test_nexa_transport.db").unwrap();
+}
+
+#[cfg(test)]
+fn user_version(path: &str) -> u32 {
+    let conn = Connection::open(path).unwrap();
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+        .unwrap()
+}
+
+#[test]
+fn test_upgrade_old_database() {
+    // the schema as the first release left it, before any migration
+    let conn = Connection::open("test_upgrade.db").unwrap();
+    conn.execute_batch(
+        "CREATE TABLE devices (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name VARCHAR(100) NOT NULL,
+            group_id INTEGER NOT NULL,
+            current_state BIT NOT NULL DEFAULT 0
+        );
+        INSERT INTO devices(id, name, group_id) VALUES(2, 'lamp', 1), (12, 'heater', 1);",
+    )
+    .unwrap();
+    drop(conn);
+    let repo = Repo::new("test_upgrade.db");
+
+    assert!(repo.ensure_updated().unwrap());
+
+    assert!(user_version("test_upgrade.db") == MIGRATIONS.len() as u32);
+    repo.set_blind_position("FQ1Q011000Q00F000", 30).unwrap();
+    // the devices that existed keep their senders and relays
+    let nexa = repo.get_nexa_transports().unwrap();
+    assert!(
+        nexa == vec![NexaTransport {
+            device_id: 2,
+            sender_id: 50331650,
+            unit: 2
+        }]
+    );
+    let relays = repo.get_relay_transports().unwrap();
+    assert!(
+        relays
+            == vec![RelayTransport {
+                device_id: 12,
+                base_url: "http://192.168.10.124".to_string(),
+                relay_id: "5".to_string()
+            }]
+    );
+    // the groups of the remotes are the senders they used to be hard coded as
+    let groups = repo.get_nexa_groups().unwrap();
+    assert!(groups.len() == 3);
+    assert!(
+        groups[2]
+            == NexaGroup {
+                name: "m3".to_string(),
+                sender_id: 50331648
+            }
+    );
+    // and the blind becomes a device in a group of its own
+    let rollos = repo.get_rollo_transports().unwrap();
+    assert!(
+        rollos
+            == vec![RolloTransport {
+                device_id: 13,
+                code: "FQ1Q011000Q00F000".to_string()
+            }]
+    );
+    let blind = repo.get_device(13).unwrap().unwrap();
+    let groups = repo.get_groups().unwrap();
+    assert!(groups.len() == 2 && groups[0].id == 1 && groups[0].name == "Group 1");
+    assert!(groups[1].id == 2 && groups[1].name == "Blinds");
+    assert!(blind.name == "Blind" && blind.group_id == 2);
+    std::fs::remove_file("test_upgrade.db").unwrap();
+}
+
+#[test]
+fn test_migrations_are_applied_once() {
+    let repo = Repo::new("test_migrations.db");
+    repo.assure_created().unwrap();
+    repo.ensure_updated().unwrap();
+    repo.set_blind_position("FQ1Q011000Q00F000", 30).unwrap();
+
+    // the first migration already ran, only the rest may run again
+    let conn = Connection::open("test_migrations.db").unwrap();
+    conn.execute_batch("DROP TABLE nexa_devices; PRAGMA user_version = 1;")
+        .unwrap();
+    drop(conn);
+    repo.ensure_updated().unwrap();
+    repo.ensure_updated().unwrap();
+
+    assert!(user_version("test_migrations.db") == MIGRATIONS.len() as u32);
+    assert!(repo.get_blind_position("FQ1Q011000Q00F000").unwrap() == Some(30));
+    // the blind is not seeded twice
+    assert!(repo.get_rollo_transports().unwrap().len() == 1);
+    assert!(repo.get_nexa_transports().is_ok());
+    std::fs::remove_file("test_migrations.db").unwrap();
+}
+
+#[test]
+fn test_schema_is_complete() {
+    let repo = Repo::new("test_schema_is_complete.db");
+    repo.assure_created().unwrap();
+    repo.ensure_updated().unwrap();
+    let conn = Connection::open("test_schema_is_complete.db").unwrap();
+    let exists = |kind: &str, name: &str| {
+        conn.prepare("SELECT name FROM sqlite_master WHERE type = ?1 AND name = ?2")
+            .unwrap()
+            .exists(params![kind, name])
+            .unwrap()
+    };
+    let foreign_keys = |table: &str| {
+        let mut statement = conn
+            .prepare("SELECT \"from\", \"table\", \"to\" FROM pragma_foreign_key_list(?1) ORDER BY \"from\"")
+            .unwrap();
+        let keys = statement
+            .query_map(params![table], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        keys.collect::<Result<Vec<(String, String, String)>>>()
+            .unwrap()
+    };
+
+    for table in [
+        "devices",
+        "device_ref_device",
+        "blind_positions",
+        "nexa_devices",
+        "rollo_devices",
+        "relay_devices",
+        "groups",
+        "nexa_senders",
+        "nexa_groups",
+        "device_aliases",
+    ] {
+        assert!(exists("table", table), "missing table {}", table);
+    }
+    assert!(exists("index", "device_ref_device_device_id"));
+    let devices_id = |column: &str| (column.to_string(), "devices".to_string(), "id".to_string());
+    assert!(
+        foreign_keys("device_ref_device")
+            == vec![devices_id("device_id"), devices_id("reference_device_id")]
+    );
+    for table in [
+        "nexa_devices",
+        "rollo_devices",
+        "relay_devices",
+        "device_aliases",
+    ] {
+        assert!(foreign_keys(table) == vec![devices_id("device_id")]);
+    }
+    std::fs::remove_file("test_schema_is_complete.db").unwrap();
+}
+
+#[test]
+fn test_groups() {
+    let repo = Repo::new("test_groups.db");
+    repo.assure_created().unwrap();
+    repo.ensure_updated().unwrap();
+    // a fresh database has the group of the blind
+    let blinds = repo.get_groups().unwrap();
+    assert!(blinds.len() == 1 && blinds[0].name == "Blinds");
+    let mut kitchen = Group {
+        id: 0,
+        name: "Kitchen".to_string(),
+        icon: "kitchen".to_string(),
+        sort_order: 2,
+    };
+    let mut bedroom = Group {
+        id: 0,
+        name: "Bedroom".to_string(),
+        icon: String::new(),
+        sort_order: 1,
+    };
+    repo.add_group(&mut kitchen).unwrap();
+    repo.add_group(&mut bedroom).unwrap();
+
+    assert!(
+        repo.get_groups().unwrap() == vec![blinds[0].clone(), bedroom.clone(), kitchen.clone()]
+    );
+
+    kitchen.name = "Kitchen and hall".to_string();
+    assert!(repo.update_group(&kitchen).unwrap());
+    assert!(repo.get_group_details(kitchen.id).unwrap() == Some(kitchen.clone()));
+    assert!(repo.delete_group(bedroom.id).unwrap());
+    assert!(!repo.delete_group(bedroom.id).unwrap());
+    assert!(!repo.update_group(&bedroom).unwrap());
+    assert!(repo.get_groups().unwrap() == vec![blinds[0].clone(), kitchen]);
+    std::fs::remove_file("test_groups.db").unwrap();
 }
@@ -0,0 +1,81 @@
+use log::info;
+use nexa_rs::nexa;
+use pulse_rs::pulse::{Jitter, Pulse, PulseSink};
+use rollo_rs::rollo;
+use std::sync::{Arc, Mutex, PoisonError};
+
+// Without the GPIO of a Pi there is no radio to send on. The simulated radio
+// decodes every frame it is handed and logs it instead, and keeps what it
+// decoded so the api can be exercised and checked on any machine.
+
+/// Transmitter sink for running without a Pi. Clones share the frames.
+#[derive(Clone, Default)]
+pub struct SimulatedRadio {
+    frames: Arc<Mutex<Vec<String>>>,
+}
+
+impl SimulatedRadio {
+    pub fn new() -> SimulatedRadio {
+        SimulatedRadio::default()
+    }
+
+    /// Every frame sent so far, decoded.
+    pub fn frames(&self) -> Vec<String> {
+        self.frames
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+fn describe(frame: &[Pulse]) -> String {
+    if let Some(command) = nexa::decode(frame) {
+        format!("nexa {:?}", command)
+    } else if let Some(command) = rollo::decode(frame) {
+        format!("rollo {} {:?}", command.code, command.direction)
+    } else {
+        format!("unknown frame of {} pulses", frame.len())
+    }
+}
+
+impl PulseSink for SimulatedRadio {
+    fn high(&mut self, _duration_us: u64) {}
+
+    fn low(&mut self, _duration_us: u64) {}
+
+    fn send(&mut self, frame: &[Pulse]) -> Jitter {
+        let decoded = describe(frame);
+        info!("Simulated {}", decoded);
+        self.frames
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(decoded);
+        Jitter {
+            edges: frame.len(),
+            ..Jitter::default()
+        }
+    }
+}
+
+#[test]
+fn test_simulated_radio_records_decoded_frames() {
+    use nexa_rs::nexa::{Channel, DeviceNumber, Unit};
+    use pulse_rs::scheduler::Transmitter;
+
+    let radio = SimulatedRadio::new();
+    let transmitter = Transmitter::spawn(radio.clone());
+    let nexa = nexa::Nexa::new(50331650, transmitter.clone()).unwrap();
+    let blinds = rollo::Rollo::new("FQ1Q011000Q00F000".parse().unwrap(), transmitter.clone());
+
+    nexa.turn_device_on(DeviceNumber::new(Channel::One, Unit::Two))
+        .wait()
+        .unwrap();
+    blinds.send(rollo::Direction::DOWN).wait().unwrap();
+    transmitter.submit(vec![]).wait().unwrap();
+
+    let frames = radio.frames();
+    assert!(frames.len() == 3);
+    assert!(frames[0].starts_with("nexa") && frames[0].contains("50331650"));
+    assert!(frames[1] == "rollo FQ1Q011000Q00F000 DOWN");
+    assert!(frames[2] == "unknown frame of 0 pulses");
+}
@@ -1,12 +1,15 @@
-use rppal::gpio::OutputPin;
+use pulse_rs::pulse::{Level, Pulse, Timing};
+use pulse_rs::scheduler::{Transmission, TransmitError, Transmitter};
+use std::collections::hash_map::RandomState;
 use std::fmt;
-use std::sync::Arc;
-use std::sync::Mutex;
-use std::thread;
-use std::time::Duration;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-const PULSE_LENGTH: u64 = 250; //unit us
-const NEXA_CHANNEL: &str = "11";
+pub const DEFAULT_TIMING: Timing = Timing {
+    pulse_length: 250,
+    repeats: 5,
+    frame_gap: 40 * 250,
+};
 
 // Nexa protocol taken from
 // Bit pattern: S HHHH HHHH HHHH HHHH HHHH HHHH HHGO CCEE P
@@ -14,29 +17,201 @@ const NEXA_CHANNEL: &str = "11";
 // h: sender id
 // g: group 0 on 1 off
 // o: on/off, 0 on 1 off
-// c: channel, nexa= 00
-// e: unit, nexa 1=11, 2 = 01, 3 = 10
+// c: channel, nexa 1=11, 2 = 01, 3 = 10, 4 = 00
+// e: unit, nexa 1=11, 2 = 01, 3 = 10, 4 = 00
 // p: pause
 // GOCCEE
 // 123456
+//
+// Dimmers: S HHHH HHHH HHHH HHHH HHHH HHHH HHGD CCEE LLLL P
+// d: dim marker sent instead of o, two short pulses
+// l: absolute dim level 0-15, msb first
 
 #[derive(Clone)]
-pub struct Nexa<'a> {
-    pub sender_id: &'a str,
-    pin: Arc<Mutex<OutputPin>>,
+pub struct Nexa {
+    sender_id: SenderId,
+    timing: Timing,
+    transmitter: Transmitter,
 }
 
-#[derive(Clone, Copy)]
-pub enum DeviceNumber {
+/// The 26 bit sender id, as printed in decimal on the remotes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SenderId(u32);
+
+#[derive(Debug)]
+pub enum NexaError {
+    InvalidSenderId(u32),
+    InvalidDimLevel(u8),
+    Transmit(TransmitError),
+}
+
+impl fmt::Display for NexaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NexaError::InvalidSenderId(id) => {
+                write!(f, "Sender id {} does not fit in 26 bits", id)
+            }
+            NexaError::InvalidDimLevel(level) => {
+                write!(f, "Dim level {} is not within 0-15", level)
+            }
+            NexaError::Transmit(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for NexaError {}
+
+impl From<TransmitError> for NexaError {
+    fn from(err: TransmitError) -> NexaError {
+        NexaError::Transmit(err)
+    }
+}
+
+impl SenderId {
+    pub const MAX: u32 = (1 << 26) - 1;
+
+    pub fn new(id: u32) -> Result<SenderId, NexaError> {
+        match id {
+            0..=SenderId::MAX => Ok(SenderId(id)),
+            _ => Err(NexaError::InvalidSenderId(id)),
+        }
+    }
+
+    pub fn value(&self) -> u32 {
+        self.0
+    }
+
+    /// A random sender id, for pairing receivers with a sender no remote
+    /// uses.
+    pub fn random() -> SenderId {
+        let mut hasher = RandomState::new().build_hasher();
+        if let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) {
+            hasher.write_u128(now.as_nanos());
+        }
+        SenderId(hasher.finish() as u32 & SenderId::MAX)
+    }
+}
+
+impl TryFrom<u32> for SenderId {
+    type Error = NexaError;
+
+    fn try_from(id: u32) -> Result<SenderId, NexaError> {
+        SenderId::new(id)
+    }
+}
+
+impl fmt::Display for SenderId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
     One,
     Two,
     Three,
+    Four,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unit {
+    One,
+    Two,
+    Three,
+    Four,
+}
+
+/// Receiver address within one sender id, 4 channels of 4 units each.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeviceNumber {
+    pub channel: Channel,
+    pub unit: Unit,
+}
+
+impl Channel {
+    fn code(&self) -> &'static str {
+        match *self {
+            Channel::One => "11",
+            Channel::Two => "01",
+            Channel::Three => "10",
+            Channel::Four => "00",
+        }
+    }
+
+    fn from_code(code: &str) -> Option<Channel> {
+        match code {
+            "11" => Some(Channel::One),
+            "01" => Some(Channel::Two),
+            "10" => Some(Channel::Three),
+            "00" => Some(Channel::Four),
+            _ => None,
+        }
+    }
+}
+
+impl Unit {
+    fn code(&self) -> &'static str {
+        match *self {
+            Unit::One => "11",
+            Unit::Two => "01",
+            Unit::Three => "10",
+            Unit::Four => "00",
+        }
+    }
+
+    fn from_code(code: &str) -> Option<Unit> {
+        match code {
+            "11" => Some(Unit::One),
+            "01" => Some(Unit::Two),
+            "10" => Some(Unit::Three),
+            "00" => Some(Unit::Four),
+            _ => None,
+        }
+    }
+}
+
+impl DeviceNumber {
+    pub fn new(channel: Channel, unit: Unit) -> DeviceNumber {
+        DeviceNumber { channel, unit }
+    }
+
+    /// Device 1-16, counting through the units of channel 1 first.
+    pub fn from_index(index: u8) -> Option<DeviceNumber> {
+        const CHANNELS: [Channel; 4] = [Channel::One, Channel::Two, Channel::Three, Channel::Four];
+        const UNITS: [Unit; 4] = [Unit::One, Unit::Two, Unit::Three, Unit::Four];
+        match index {
+            1..=16 => {
+                let index = (index - 1) as usize;
+                Some(DeviceNumber::new(CHANNELS[index / 4], UNITS[index % 4]))
+            }
+            _ => None,
+        }
+    }
+
+    /// Inverse of from_index.
+    pub fn index(&self) -> u8 {
+        let channel = match self.channel {
+            Channel::One => 0,
+            Channel::Two => 1,
+            Channel::Three => 2,
+            Channel::Four => 3,
+        };
+        let unit = match self.unit {
+            Unit::One => 1,
+            Unit::Two => 2,
+            Unit::Three => 3,
+            Unit::Four => 4,
+        };
+        channel * 4 + unit
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum DeviceMode {
     On,
     Off,
+    Dim(u8),
 }
 
 impl fmt::Display for DeviceMode {
@@ -44,112 +219,624 @@ impl fmt::Display for DeviceMode {
         match *self {
             DeviceMode::On => write!(f, "0"),
             DeviceMode::Off => write!(f, "1"),
+            DeviceMode::Dim(_) => write!(f, "D"),
         }
     }
 }
 
-impl Nexa<'_> {
-    pub fn new(sender_id: &str, pin: Arc<Mutex<OutputPin>>) -> Nexa {
-        assert!(sender_id.len() == 26);
-        Nexa { sender_id, pin }
+impl Nexa {
+    pub fn new(sender_id: u32, transmitter: Transmitter) -> Result<Nexa, NexaError> {
+        Ok(Nexa {
+            sender_id: SenderId::new(sender_id)?,
+            timing: DEFAULT_TIMING,
+            transmitter,
+        })
     }
 
-    fn get_code(&self, whole_group: bool, device_no: DeviceNumber, mode: DeviceMode) -> String {
-        let device_id = match device_no {
-            DeviceNumber::One => "11",
-            DeviceNumber::Two => "01",
-            DeviceNumber::Three => "10",
-        };
-        let group_control = match whole_group {
-            true => '0',
-            false => '1',
-        };
-        format!(
-            "{}{}{}{}{}",
-            self.sender_id, group_control, mode, NEXA_CHANNEL, device_id
+    pub fn with_timing(mut self, timing: Timing) -> Nexa {
+        self.timing = timing;
+        self
+    }
+
+    pub fn with_pulse_length(mut self, pulse_length: u64) -> Nexa {
+        self.timing.pulse_length = pulse_length;
+        self
+    }
+
+    pub fn with_repeats(mut self, repeats: usize) -> Nexa {
+        self.timing.repeats = repeats;
+        self
+    }
+
+    pub fn with_frame_gap(mut self, frame_gap: u64) -> Nexa {
+        self.timing.frame_gap = frame_gap;
+        self
+    }
+
+    pub fn sender_id(&self) -> SenderId {
+        self.sender_id
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    pub fn turn_device_on(&self, device_no: DeviceNumber) -> Transmission {
+        self.send(false, device_no, DeviceMode::On)
+    }
+
+    pub fn turn_device_off(&self, device_no: DeviceNumber) -> Transmission {
+        self.send(false, device_no, DeviceMode::Off)
+    }
+
+    pub fn turn_group_off(&self) -> Transmission {
+        self.send(
+            true,
+            DeviceNumber::new(Channel::One, Unit::One),
+            DeviceMode::Off,
         )
     }
 
-    pub fn turn_device_on(&self, device_no: DeviceNumber) {
-        for _ in 0..5 {
-            let code = self.get_code(false, device_no, DeviceMode::On);
-            self.write_code(&code)
-        }
+    pub fn turn_group_on(&self) -> Transmission {
+        self.send(
+            true,
+            DeviceNumber::new(Channel::One, Unit::One),
+            DeviceMode::On,
+        )
     }
 
-    pub fn turn_device_off(&self, device_no: DeviceNumber) {
-        let code = self.get_code(false, device_no, DeviceMode::Off);
-        for _ in 0..5 {
-            self.write_code(&code)
-        }
+    /// Self-learning receivers learn the code of the first ON they receive
+    /// within a few seconds of being powered up.
+    pub fn pair(&self, device_no: DeviceNumber) -> Transmission {
+        self.turn_device_on(device_no)
     }
 
-    pub fn turn_group_off(&self) {
-        let code = self.get_code(true, DeviceNumber::One, DeviceMode::Off);
-        for _ in 0..5 {
-            self.write_code(&code);
-        }
+    /// Receivers in learning mode forget a code when they receive OFF from
+    /// it.
+    pub fn unpair(&self, device_no: DeviceNumber) -> Transmission {
+        self.turn_device_off(device_no)
+    }
+
+    pub fn set_dim_level(
+        &self,
+        device_no: DeviceNumber,
+        level: u8,
+    ) -> Result<Transmission, NexaError> {
+        let mode = DeviceMode::Dim(level);
+        mode.validate()?;
+        Ok(self.send(false, device_no, mode))
+    }
+
+    fn send(&self, whole_group: bool, device_no: DeviceNumber, mode: DeviceMode) -> Transmission {
+        let frame = encode_frame(self.sender_id, whole_group, device_no, mode, &self.timing);
+        self.transmitter.submit(frame)
     }
+}
 
-    pub fn turn_group_on(&self) {
-        let code = self.get_code(true, DeviceNumber::One, DeviceMode::On);
-        for _ in 0..5 {
-            self.write_code(&code);
+impl DeviceMode {
+    fn validate(&self) -> Result<(), NexaError> {
+        match *self {
+            DeviceMode::Dim(level) if level > 15 => Err(NexaError::InvalidDimLevel(level)),
+            _ => Ok(()),
         }
     }
+}
 
-    fn write_code(&self, code: &str) {
-        assert!(code.len() == 32);
-        self.send_sync();
-        for c in code.chars() {
-            match c {
-                '1' => self.send_one(),
-                '0' => self.send_zero(),
-                _ => panic!("Illegal code"),
-            }
+/// Encodes a command into the pulses sent on air, including the sync, the
+/// trailing pause and all repeats. Nothing is transmitted.
+pub fn encode(
+    sender_id: SenderId,
+    whole_group: bool,
+    device_no: DeviceNumber,
+    mode: DeviceMode,
+    timing: &Timing,
+) -> Result<Vec<Pulse>, NexaError> {
+    mode.validate()?;
+    Ok(encode_frame(
+        sender_id,
+        whole_group,
+        device_no,
+        mode,
+        timing,
+    ))
+}
+
+fn encode_frame(
+    sender_id: SenderId,
+    whole_group: bool,
+    device_no: DeviceNumber,
+    mode: DeviceMode,
+    timing: &Timing,
+) -> Vec<Pulse> {
+    let code = get_code(sender_id, whole_group, device_no, mode);
+    let mut frame = vec![];
+    for _ in 0..timing.repeats {
+        write_code(&code, timing, &mut frame);
+    }
+    frame
+}
+
+#[derive(Clone, Copy)]
+enum Bit {
+    Zero,
+    One,
+    Dim,
+}
+
+fn binary(code: &str) -> impl Iterator<Item = Bit> + '_ {
+    code.chars().map(|c| match c {
+        '1' => Bit::One,
+        _ => Bit::Zero,
+    })
+}
+
+fn get_code(
+    sender_id: SenderId,
+    whole_group: bool,
+    device_no: DeviceNumber,
+    mode: DeviceMode,
+) -> Vec<Bit> {
+    let mut code: Vec<Bit> = binary(&format!("{:026b}", sender_id.value())).collect();
+    code.push(match whole_group {
+        true => Bit::Zero,
+        false => Bit::One,
+    });
+    code.push(match mode {
+        DeviceMode::On => Bit::Zero,
+        DeviceMode::Off => Bit::One,
+        DeviceMode::Dim(_) => Bit::Dim,
+    });
+    code.extend(binary(device_no.channel.code()));
+    code.extend(binary(device_no.unit.code()));
+    // Bit::One goes on air as a standard 0, so the level is complemented to
+    // reach the receiver as plain binary
+    if let DeviceMode::Dim(level) = mode {
+        code.extend(binary(&format!("{:04b}", 15 - level)));
+    }
+    code
+}
+
+fn write_code(code: &[Bit], timing: &Timing, frame: &mut Vec<Pulse>) {
+    let t = timing.pulse_length;
+    send_sync(t, frame);
+    for bit in code {
+        match bit {
+            Bit::One => send_one(t, frame),
+            Bit::Zero => send_zero(t, frame),
+            Bit::Dim => send_dim(t, frame),
         }
-        self.send_pause();
     }
+    send_pause(t, timing.frame_gap, frame);
+}
+
+fn send_zero(t: u64, frame: &mut Vec<Pulse>) {
+    send_physical_zero(t, frame);
+    send_physical_one(t, frame);
+}
+
+fn send_one(t: u64, frame: &mut Vec<Pulse>) {
+    send_physical_one(t, frame);
+    send_physical_zero(t, frame);
+}
+
+fn send_dim(t: u64, frame: &mut Vec<Pulse>) {
+    send_physical_one(t, frame);
+    send_physical_one(t, frame);
+}
+
+fn send_physical_one(t: u64, frame: &mut Vec<Pulse>) {
+    frame.push((Level::High, t));
+    frame.push((Level::Low, t));
+}
+
+fn send_physical_zero(t: u64, frame: &mut Vec<Pulse>) {
+    frame.push((Level::High, t));
+    frame.push((Level::Low, 5 * t));
+}
+
+fn send_sync(t: u64, frame: &mut Vec<Pulse>) {
+    frame.push((Level::High, t));
+    frame.push((Level::Low, 10 * t));
+}
 
-    fn send_zero(&self) {
-        self.send_physical_zero();
-        self.send_physical_one();
+fn send_pause(t: u64, frame_gap: u64, frame: &mut Vec<Pulse>) {
+    frame.push((Level::High, t));
+    frame.push((Level::Low, frame_gap));
+}
+
+/// A command as sent on air, decoded from a received frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Command {
+    pub sender_id: SenderId,
+    pub whole_group: bool,
+    pub device: DeviceNumber,
+    pub mode: DeviceMode,
+}
+
+/// Decodes the first complete frame found in the pulses. The pulse length is
+/// taken from the sync of each frame, so remotes with slightly different
+/// timing decode as well.
+pub fn decode(pulses: &[Pulse]) -> Option<Command> {
+    (0..pulses.len()).find_map(|start| decode_frame(&pulses[start..]))
+}
+
+fn decode_frame(pulses: &[Pulse]) -> Option<Command> {
+    let (sync_high, sync_low, bits) = match pulses {
+        [(Level::High, high), (Level::Low, low), bits @ ..] => (*high, *low, bits),
+        _ => return None,
+    };
+    // receiver modules stretch the highs, the sync low is a better reference
+    let t = sync_low / 10;
+    if t == 0 || sync_high > 3 * t {
+        return None;
     }
 
-    fn send_one(&self) {
-        self.send_physical_one();
-        self.send_physical_zero();
+    let mut code = String::new();
+    for bit in bits.chunks(4).take(36) {
+        match read_bit(bit, t) {
+            Some(c) => code.push(c),
+            None => break,
+        }
     }
+    parse_code(&code)
+}
 
-    fn send_physical_one(&self) {
-        let mut pin = self.pin.lock().unwrap();
-        pin.set_high();
-        thread::sleep(Duration::from_micros(PULSE_LENGTH));
-        pin.set_low();
-        thread::sleep(Duration::from_micros(PULSE_LENGTH));
+fn read_bit(bit: &[Pulse], t: u64) -> Option<char> {
+    let (first, second) = match *bit {
+        [(Level::High, h1), (Level::Low, first), (Level::High, h2), (Level::Low, second)]
+            if h1 < 3 * t && h2 < 3 * t =>
+        {
+            (first, second)
+        }
+        _ => return None,
+    };
+    let is_short = |gap: u64| gap < 3 * t;
+    let is_long = |gap: u64| (3 * t..8 * t).contains(&gap);
+    if is_short(first) && is_long(second) {
+        Some('1')
+    } else if is_long(first) && is_short(second) {
+        Some('0')
+    } else if is_short(first) && is_short(second) {
+        Some('D')
+    } else {
+        None
     }
+}
+
+fn parse_code(code: &str) -> Option<Command> {
+    let mode = match (code.len(), code.get(27..28)) {
+        (32, Some("0")) => DeviceMode::On,
+        (32, Some("1")) => DeviceMode::Off,
+        (36, Some("D")) => DeviceMode::Dim(15 - u8::from_str_radix(&code[32..36], 2).ok()?),
+        _ => return None,
+    };
+    let sender_id = u32::from_str_radix(&code[0..26], 2).ok()?;
+    Some(Command {
+        sender_id: SenderId::new(sender_id).ok()?,
+        whole_group: &code[26..27] == "0",
+        device: DeviceNumber::new(
+            Channel::from_code(&code[28..30])?,
+            Unit::from_code(&code[30..32])?,
+        ),
+        mode,
+    })
+}
+
+#[cfg(test)]
+fn expected_pulses(code: &str) -> Vec<Pulse> {
+    use Level::{High, Low};
+    const PULSE_LENGTH: u64 = 250;
 
-    fn send_physical_zero(&self) {
-        let mut pin = self.pin.lock().unwrap();
-        pin.set_high();
-        thread::sleep(Duration::from_micros(PULSE_LENGTH));
-        pin.set_low();
-        thread::sleep(Duration::from_micros(5 * PULSE_LENGTH));
+    let mut pulses = vec![(High, PULSE_LENGTH), (Low, 10 * PULSE_LENGTH)];
+    for c in code.chars() {
+        let (first, second) = match c {
+            '1' => (PULSE_LENGTH, 5 * PULSE_LENGTH),
+            'D' => (PULSE_LENGTH, PULSE_LENGTH),
+            _ => (5 * PULSE_LENGTH, PULSE_LENGTH),
+        };
+        pulses.extend([(High, PULSE_LENGTH), (Low, first)]);
+        pulses.extend([(High, PULSE_LENGTH), (Low, second)]);
     }
+    pulses.extend([(High, PULSE_LENGTH), (Low, 40 * PULSE_LENGTH)]);
+    pulses.repeat(5)
+}
+
+#[test]
+fn test_turn_device_on_pulses() {
+    use pulse_rs::pulse::Recorder;
+    use pulse_rs::scheduler::Transmitter;
+
+    let recorder = Recorder::new();
+    let nexa = Nexa::new(50331650, Transmitter::spawn(recorder.clone())).unwrap();
+
+    nexa.turn_device_on(DeviceNumber::new(Channel::One, Unit::Two))
+        .wait()
+        .unwrap();
+
+    let pulses = recorder.pulses();
+    assert!(pulses.len() == 5 * (2 + 32 * 4 + 2));
+    // G O CC EE: single device, on, channel 11, unit 01
+    assert!(pulses == expected_pulses(concat!("11000000000000000000000010", "101101")));
+}
+
+#[test]
+fn test_turn_group_off_pulses() {
+    use pulse_rs::pulse::Recorder;
+    use pulse_rs::scheduler::Transmitter;
+
+    let recorder = Recorder::new();
+    let nexa = Nexa::new(50331649, Transmitter::spawn(recorder.clone())).unwrap();
+
+    nexa.turn_group_off().wait().unwrap();
+
+    assert!(recorder.pulses() == expected_pulses(concat!("11000000000000000000000001", "011111")));
+}
 
-    fn send_sync(&self) {
-        let mut pin = self.pin.lock().unwrap();
-        pin.set_high();
-        thread::sleep(Duration::from_micros(PULSE_LENGTH));
-        pin.set_low();
-        thread::sleep(Duration::from_micros(10 * PULSE_LENGTH));
+#[test]
+fn test_encode_matches_transmission() {
+    use pulse_rs::pulse::Recorder;
+    use pulse_rs::scheduler::Transmitter;
+
+    let recorder = Recorder::new();
+    let nexa = Nexa::new(50331651, Transmitter::spawn(recorder.clone())).unwrap();
+
+    nexa.turn_device_off(DeviceNumber::new(Channel::One, Unit::Three))
+        .wait()
+        .unwrap();
+
+    let frame = encode(
+        SenderId::new(50331651).unwrap(),
+        false,
+        DeviceNumber::new(Channel::One, Unit::Three),
+        DeviceMode::Off,
+        &DEFAULT_TIMING,
+    )
+    .unwrap();
+    assert!(recorder.pulses() == frame);
+    assert!(frame == expected_pulses(concat!("11000000000000000000000011", "111110")));
+}
+
+#[test]
+fn test_set_dim_level_pulses() {
+    use pulse_rs::pulse::Recorder;
+    use pulse_rs::scheduler::Transmitter;
+
+    let recorder = Recorder::new();
+    let nexa = Nexa::new(50331650, Transmitter::spawn(recorder.clone())).unwrap();
+
+    nexa.set_dim_level(DeviceNumber::new(Channel::One, Unit::One), 9)
+        .unwrap()
+        .wait()
+        .unwrap();
+
+    let pulses = recorder.pulses();
+    assert!(pulses.len() == 5 * (2 + 36 * 4 + 2));
+    // G D CC EE LLLL
+    assert!(pulses == expected_pulses(concat!("11000000000000000000000010", "1D11110110")));
+}
+
+#[test]
+fn test_set_dim_level_out_of_range() {
+    use pulse_rs::pulse::Recorder;
+    use pulse_rs::scheduler::Transmitter;
+
+    let recorder = Recorder::new();
+    let nexa = Nexa::new(50331650, Transmitter::spawn(recorder.clone())).unwrap();
+
+    let result = nexa.set_dim_level(DeviceNumber::new(Channel::One, Unit::One), 16);
+
+    assert!(matches!(result, Err(NexaError::InvalidDimLevel(16))));
+    let frame = encode(
+        nexa.sender_id(),
+        false,
+        DeviceNumber::new(Channel::One, Unit::One),
+        DeviceMode::Dim(16),
+        &DEFAULT_TIMING,
+    );
+    assert!(matches!(frame, Err(NexaError::InvalidDimLevel(16))));
+    assert!(recorder.pulses().is_empty());
+}
+
+#[test]
+fn test_channel_and_unit_bits() {
+    let frame = encode(
+        SenderId::new(50331650).unwrap(),
+        false,
+        DeviceNumber::new(Channel::Four, Unit::Three),
+        DeviceMode::On,
+        &DEFAULT_TIMING,
+    )
+    .unwrap();
+
+    // G O CC EE
+    assert!(frame == expected_pulses(concat!("11000000000000000000000010", "100010")));
+}
+
+#[test]
+fn test_device_number_from_index() {
+    assert!(DeviceNumber::from_index(0).is_none());
+    assert!(DeviceNumber::from_index(1) == Some(DeviceNumber::new(Channel::One, Unit::One)));
+    assert!(DeviceNumber::from_index(6) == Some(DeviceNumber::new(Channel::Two, Unit::Two)));
+    assert!(DeviceNumber::from_index(16) == Some(DeviceNumber::new(Channel::Four, Unit::Four)));
+    assert!(DeviceNumber::from_index(17).is_none());
+}
+
+#[test]
+fn test_device_number_index() {
+    for index in 1..=16 {
+        assert!(DeviceNumber::from_index(index).unwrap().index() == index);
     }
+}
 
-    fn send_pause(&self) {
-        let mut pin = self.pin.lock().unwrap();
-        pin.set_high();
-        thread::sleep(Duration::from_micros(PULSE_LENGTH));
-        pin.set_low();
-        thread::sleep(Duration::from_micros(40 * PULSE_LENGTH));
+#[test]
+fn test_random_sender_id_is_valid() {
+    for _ in 0..100 {
+        assert!(SenderId::random().value() <= SenderId::MAX);
     }
+    assert!(SenderId::random() != SenderId::random());
+}
+
+#[test]
+fn test_pair_and_unpair_send_on_and_off() {
+    use pulse_rs::pulse::Recorder;
+    use pulse_rs::scheduler::Transmitter;
+
+    let recorder = Recorder::new();
+    let nexa = Nexa::new(50331650, Transmitter::spawn(recorder.clone())).unwrap();
+    let device = DeviceNumber::new(Channel::One, Unit::Two);
+
+    nexa.pair(device).wait().unwrap();
+    nexa.unpair(device).wait().unwrap();
+
+    let sender_id = SenderId::new(50331650).unwrap();
+    let mut expected = encode(sender_id, false, device, DeviceMode::On, &nexa.timing).unwrap();
+    expected.extend(encode(sender_id, false, device, DeviceMode::Off, &nexa.timing).unwrap());
+    assert!(recorder.pulses() == expected);
+}
+
+#[test]
+fn test_sender_id_range() {
+    use pulse_rs::pulse::Recorder;
+    use pulse_rs::scheduler::Transmitter;
+
+    assert!(SenderId::new(0).is_ok());
+    assert!(SenderId::new(SenderId::MAX).unwrap().value() == 67108863);
+    assert!(matches!(
+        SenderId::new(1 << 26),
+        Err(NexaError::InvalidSenderId(id)) if id == 1 << 26
+    ));
+    assert!(Nexa::new(u32::MAX, Transmitter::spawn(Recorder::new())).is_err());
+}
+
+#[test]
+fn test_configured_timing() {
+    use pulse_rs::pulse::Recorder;
+    use pulse_rs::scheduler::Transmitter;
+
+    let recorder = Recorder::new();
+    let nexa = Nexa::new(50331650, Transmitter::spawn(recorder.clone()))
+        .unwrap()
+        .with_pulse_length(300)
+        .with_repeats(2)
+        .with_frame_gap(20000);
+
+    nexa.turn_device_on(DeviceNumber::new(Channel::One, Unit::One))
+        .wait()
+        .unwrap();
+
+    let pulses = recorder.pulses();
+    assert!(pulses.len() == 2 * (2 + 32 * 4 + 2));
+    assert!(pulses[0] == (Level::High, 300));
+    assert!(pulses[1] == (Level::Low, 3000));
+    assert!(pulses[2 + 32 * 4 + 1] == (Level::Low, 20000));
+    assert!(pulses
+        .iter()
+        .filter(|p| p.0 == Level::High)
+        .all(|p| p.1 == 300));
+}
+
+#[test]
+fn test_decode_round_trip() {
+    let sender_id = SenderId::new(50331650).unwrap();
+    for (whole_group, device, mode) in [
+        (
+            false,
+            DeviceNumber::new(Channel::One, Unit::Two),
+            DeviceMode::On,
+        ),
+        (
+            false,
+            DeviceNumber::new(Channel::Four, Unit::Three),
+            DeviceMode::Off,
+        ),
+        (
+            true,
+            DeviceNumber::new(Channel::One, Unit::One),
+            DeviceMode::Off,
+        ),
+        (
+            false,
+            DeviceNumber::new(Channel::Two, Unit::Four),
+            DeviceMode::Dim(11),
+        ),
+    ] {
+        let frame = encode(sender_id, whole_group, device, mode, &DEFAULT_TIMING).unwrap();
+        let expected = Command {
+            sender_id,
+            whole_group,
+            device,
+            mode,
+        };
+        assert!(decode(&frame) == Some(expected));
+    }
+}
+
+#[test]
+fn test_decode_distorted_frame() {
+    let frame = encode(
+        SenderId::new(12345678).unwrap(),
+        false,
+        DeviceNumber::new(Channel::Three, Unit::One),
+        DeviceMode::On,
+        &DEFAULT_TIMING,
+    )
+    .unwrap();
+    // a receiver module stretches highs and the timestamps are a bit off
+    let distorted: Vec<Pulse> = std::iter::once((Level::Low, 31000))
+        .chain(
+            frame
+                .iter()
+                .enumerate()
+                .map(|(i, &(level, duration))| match level {
+                    Level::High => (level, duration + 90),
+                    Level::Low if i % 2 == 0 => (level, duration * 4 / 5),
+                    Level::Low => (level, duration * 6 / 5),
+                }),
+        )
+        .collect();
+
+    let command = decode(&distorted).unwrap();
+    assert!(command.sender_id.value() == 12345678);
+    assert!(command.device == DeviceNumber::new(Channel::Three, Unit::One));
+    assert!(command.mode == DeviceMode::On);
+}
+
+#[test]
+fn test_decode_rejects_noise() {
+    let noise: Vec<Pulse> = (0..200)
+        .map(|i| match i % 2 {
+            0 => (Level::High, 100 + i * 7 % 300),
+            _ => (Level::Low, 100 + i * 13 % 900),
+        })
+        .collect();
+
+    assert!(decode(&noise).is_none());
+    assert!(decode(&[]).is_none());
+}
+
+// There is no capture of a real remote in the repo yet, `rollo-tester
+// record` saves one to check decode against.
+#[test]
+fn test_decode_replayed_capture() {
+    let command = Command {
+        sender_id: SenderId::new(50331650).unwrap(),
+        whole_group: false,
+        device: DeviceNumber::new(Channel::One, Unit::Two),
+        mode: DeviceMode::On,
+    };
+    let frame = encode(
+        command.sender_id,
+        command.whole_group,
+        command.device,
+        command.mode,
+        &DEFAULT_TIMING,
+    )
+    .unwrap();
+
+    let mut written = vec![];
+    pulse_rs::capture::write(&mut written, &frame).unwrap();
+    let replayed = pulse_rs::capture::read(&written[..]).unwrap();
+
+    assert!(replayed == frame);
+    assert!(decode(&replayed) == Some(command));
 }
@@ -0,0 +1,6 @@
+pub mod capture;
+pub mod pulse;
+pub mod realtime;
+pub mod receiver;
+pub mod scheduler;
+pub mod tristate;
@@ -0,0 +1,200 @@
+use rppal::gpio::OutputPin;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// A 433 MHz transmitter only knows two things: carrier on for a while and
+// carrier off for a while. The encoders write to a PulseSink so the same code
+// can drive a GPIO pin on the Pi or be recorded and inspected anywhere else.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    High,
+    Low,
+}
+
+pub type Pulse = (Level, u64); //level, duration in us
+
+/// Timing of a protocol instance. Encoders express their pulses as multiples
+/// of pulse_length, send the frame repeats times and keep the transmitter
+/// silent for frame_gap after each repeat.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timing {
+    pub pulse_length: u64, //unit us
+    pub repeats: usize,
+    pub frame_gap: u64, //unit us
+}
+
+pub trait PulseSink {
+    fn high(&mut self, duration_us: u64);
+    fn low(&mut self, duration_us: u64);
+
+    /// Sends a whole frame and reports how late its edges were.
+    fn send(&mut self, frame: &[Pulse]) -> Jitter {
+        let mut meter = JitterMeter::start();
+        for &(level, duration_us) in frame {
+            meter.edge();
+            match level {
+                Level::High => self.high(duration_us),
+                Level::Low => self.low(duration_us),
+            }
+            meter.advance(duration_us);
+        }
+        meter.finish()
+    }
+}
+
+/// How late the edges of a frame came compared to the schedule the frame
+/// describes, counted from the first edge.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Jitter {
+    pub edges: usize,
+    pub max_us: u64,
+    pub mean_us: u64,
+}
+
+impl fmt::Display for Jitter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} edges, max {} us, mean {} us late",
+            self.edges, self.max_us, self.mean_us
+        )
+    }
+}
+
+pub struct JitterMeter {
+    start: Instant,
+    scheduled_us: u64,
+    edges: usize,
+    total_us: u64,
+    max_us: u64,
+}
+
+impl JitterMeter {
+    pub fn start() -> JitterMeter {
+        JitterMeter {
+            start: Instant::now(),
+            scheduled_us: 0,
+            edges: 0,
+            total_us: 0,
+            max_us: 0,
+        }
+    }
+
+    /// Records an edge happening now.
+    pub fn edge(&mut self) {
+        let elapsed_us = self.start.elapsed().as_micros() as u64;
+        let late_us = elapsed_us.saturating_sub(self.scheduled_us);
+        self.edges += 1;
+        self.total_us += late_us;
+        self.max_us = self.max_us.max(late_us);
+    }
+
+    /// Moves the schedule past a pulse of the given length.
+    pub fn advance(&mut self, duration_us: u64) {
+        self.scheduled_us += duration_us;
+    }
+
+    pub fn finish(self) -> Jitter {
+        Jitter {
+            edges: self.edges,
+            max_us: self.max_us,
+            mean_us: match self.edges {
+                0 => 0,
+                edges => self.total_us / edges as u64,
+            },
+        }
+    }
+}
+
+impl PulseSink for OutputPin {
+    fn high(&mut self, duration_us: u64) {
+        self.set_high();
+        thread::sleep(Duration::from_micros(duration_us));
+    }
+
+    fn low(&mut self, duration_us: u64) {
+        self.set_low();
+        thread::sleep(Duration::from_micros(duration_us));
+    }
+}
+
+/// Sink that keeps every pulse in memory instead of transmitting it. Clones
+/// share the same buffer, so a test can hand one clone to an encoder and read
+/// the pulse train back from another.
+#[derive(Clone, Default)]
+pub struct Recorder {
+    pulses: Arc<Mutex<Vec<Pulse>>>,
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder::default()
+    }
+
+    pub fn pulses(&self) -> Vec<Pulse> {
+        self.lock().clone()
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    // A panic while recording cannot leave the buffer half updated, so a
+    // poisoned lock is as good as any other.
+    fn lock(&self) -> MutexGuard<'_, Vec<Pulse>> {
+        self.pulses.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl PulseSink for Recorder {
+    fn high(&mut self, duration_us: u64) {
+        self.lock().push((Level::High, duration_us));
+    }
+
+    fn low(&mut self, duration_us: u64) {
+        self.lock().push((Level::Low, duration_us));
+    }
+}
+
+#[test]
+fn test_recorder_clones_share_pulses() {
+    let recorder = Recorder::new();
+    let mut sink = recorder.clone();
+
+    sink.high(250);
+    sink.low(750);
+
+    assert!(recorder.pulses() == vec![(Level::High, 250), (Level::Low, 750)]);
+
+    recorder.clear();
+    assert!(recorder.pulses().is_empty());
+}
+
+#[test]
+fn test_send_reports_late_edges() {
+    struct SlowSink;
+
+    impl PulseSink for SlowSink {
+        fn high(&mut self, _duration_us: u64) {
+            thread::sleep(Duration::from_millis(2));
+        }
+
+        fn low(&mut self, _duration_us: u64) {
+            thread::sleep(Duration::from_millis(2));
+        }
+    }
+
+    let jitter = SlowSink.send(&[(Level::High, 100), (Level::Low, 100), (Level::High, 100)]);
+
+    assert!(jitter.edges == 3);
+    // the third edge is scheduled at 200 us but comes after two 2 ms sleeps
+    assert!(jitter.max_us >= 3800);
+    assert!(jitter.mean_us > 0);
+
+    let jitter = Recorder::new().send(&[(Level::High, 100), (Level::Low, 100)]);
+    assert!(jitter.edges == 2);
+    assert!(jitter.max_us == 0);
+}
@@ -0,0 +1,125 @@
+use crate::pulse::{Jitter, JitterMeter, Level, Pulse, PulseSink};
+use rppal::gpio::OutputPin;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// thread::sleep on a loaded Pi regularly overshoots by hundreds of
+// microseconds, which is longer than a Nexa pulse. Sleep only until shortly
+// before each deadline and busy-wait for the rest, and schedule every edge
+// from the start of the frame so that a late edge does not push all the
+// following ones. The realtime priority is given up while sleeping through
+// long lows, such as the gaps between repeats, so other threads get the core
+// of a single-core Pi between the bursts of pulses.
+const SPIN_MARGIN: Duration = Duration::from_micros(2000);
+const REALTIME_PRIORITY: libc::c_int = 50;
+
+/// Output pin with accurate pulse timing, for use on the Pi instead of a
+/// bare OutputPin.
+pub struct PrecisePin {
+    pin: OutputPin,
+}
+
+impl PrecisePin {
+    pub fn new(pin: OutputPin) -> PrecisePin {
+        PrecisePin { pin }
+    }
+}
+
+impl PulseSink for PrecisePin {
+    fn high(&mut self, duration_us: u64) {
+        let deadline = Instant::now() + Duration::from_micros(duration_us);
+        self.pin.set_high();
+        wait_until(deadline);
+    }
+
+    fn low(&mut self, duration_us: u64) {
+        let deadline = Instant::now() + Duration::from_micros(duration_us);
+        self.pin.set_low();
+        wait_until(deadline);
+    }
+
+    fn send(&mut self, frame: &[Pulse]) -> Jitter {
+        let mut priority = RealtimePriority::raise();
+        let mut meter = JitterMeter::start();
+        let mut deadline = Instant::now();
+        for &(level, duration_us) in frame {
+            wait_until(deadline);
+            meter.edge();
+            match level {
+                Level::High => self.pin.set_high(),
+                Level::Low => self.pin.set_low(),
+            }
+            meter.advance(duration_us);
+            deadline += Duration::from_micros(duration_us);
+            if level == Level::Low && Duration::from_micros(duration_us) > SPIN_MARGIN {
+                drop(priority);
+                thread::sleep(deadline.saturating_duration_since(Instant::now() + SPIN_MARGIN));
+                priority = RealtimePriority::raise();
+            }
+        }
+        wait_until(deadline);
+        meter.finish()
+    }
+}
+
+pub fn wait_until(deadline: Instant) {
+    let now = Instant::now();
+    if deadline > now + SPIN_MARGIN {
+        thread::sleep(deadline - now - SPIN_MARGIN);
+    }
+    while Instant::now() < deadline {
+        std::hint::spin_loop();
+    }
+}
+
+/// Runs the current thread with the SCHED_FIFO policy while alive and
+/// restores the previous policy when dropped. This needs root or
+/// CAP_SYS_NICE; without them the thread just keeps its normal priority.
+pub struct RealtimePriority {
+    previous: Option<(libc::c_int, libc::sched_param)>,
+}
+
+impl RealtimePriority {
+    pub fn raise() -> RealtimePriority {
+        // SAFETY: plain syscalls on the calling thread with valid pointers
+        let previous = unsafe {
+            let policy = libc::sched_getscheduler(0);
+            let mut param: libc::sched_param = std::mem::zeroed();
+            let mut realtime: libc::sched_param = std::mem::zeroed();
+            realtime.sched_priority = REALTIME_PRIORITY;
+            if policy >= 0
+                && libc::sched_getparam(0, &mut param) == 0
+                && libc::sched_setscheduler(0, libc::SCHED_FIFO, &realtime) == 0
+            {
+                Some((policy, param))
+            } else {
+                None
+            }
+        };
+        RealtimePriority { previous }
+    }
+
+    pub fn is_raised(&self) -> bool {
+        self.previous.is_some()
+    }
+}
+
+impl Drop for RealtimePriority {
+    fn drop(&mut self) {
+        if let Some((policy, param)) = self.previous {
+            // SAFETY: restores the values read from this thread in raise
+            unsafe {
+                libc::sched_setscheduler(0, policy, &param);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_wait_until_does_not_return_early() {
+    for micros in [50, 500, 3000] {
+        let deadline = Instant::now() + Duration::from_micros(micros);
+        wait_until(deadline);
+        assert!(Instant::now() >= deadline);
+    }
+}
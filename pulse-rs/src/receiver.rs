@@ -0,0 +1,103 @@
+use crate::pulse::{Level, Pulse};
+use rppal::gpio::{self, InputPin, Trigger};
+use std::sync::mpsc;
+use std::time::Instant;
+
+// Frames are separated by silence on air. The pauses between Nexa repeats are
+// 10 ms, while no protocol we decode keeps the carrier off for more than a
+// few ms inside a frame.
+const FRAME_GAP_US: u64 = 5000;
+const MIN_FRAME_PULSES: usize = 16;
+
+/// Samples a 433 MHz receiver module on an input pin. Every edge is
+/// timestamped in the interrupt handler and turned into a pulse, the level
+/// that just ended and how long it lasted.
+pub struct Receiver {
+    _pin: InputPin,
+    pulses: mpsc::Receiver<Pulse>,
+}
+
+impl Receiver {
+    pub fn new(mut pin: InputPin) -> Result<Receiver, gpio::Error> {
+        let (sender, pulses) = mpsc::channel();
+        let mut last_edge = Instant::now();
+        pin.set_async_interrupt(Trigger::Both, move |level| {
+            let now = Instant::now();
+            let duration_us = (now - last_edge).as_micros() as u64;
+            last_edge = now;
+            let ended = match level {
+                gpio::Level::High => Level::Low,
+                gpio::Level::Low => Level::High,
+            };
+            // nobody listening any more, the receiver is being dropped
+            let _ = sender.send((ended, duration_us));
+        })?;
+        Ok(Receiver { _pin: pin, pulses })
+    }
+
+    /// Blocks until the next frame has been received.
+    pub fn recv_frame(&self) -> Option<Vec<Pulse>> {
+        let mut splitter = FrameSplitter::new();
+        for pulse in self.pulses.iter() {
+            if let Some(frame) = splitter.push(pulse) {
+                return Some(frame);
+            }
+        }
+        None
+    }
+}
+
+/// Groups a stream of pulses into frames, cutting after every low period
+/// long enough to be a gap between frames. Bursts too short to be a frame are
+/// dropped as noise.
+#[derive(Default)]
+pub struct FrameSplitter {
+    current: Vec<Pulse>,
+}
+
+impl FrameSplitter {
+    pub fn new() -> FrameSplitter {
+        FrameSplitter::default()
+    }
+
+    pub fn push(&mut self, pulse: Pulse) -> Option<Vec<Pulse>> {
+        self.current.push(pulse);
+        match pulse {
+            (Level::Low, duration_us) if duration_us >= FRAME_GAP_US => {
+                let frame = std::mem::take(&mut self.current);
+                match frame.len() >= MIN_FRAME_PULSES {
+                    true => Some(frame),
+                    false => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn test_frame_splitter() {
+    let mut splitter = FrameSplitter::new();
+    let frame: Vec<Pulse> = [(Level::High, 250), (Level::Low, 1250)]
+        .repeat(10)
+        .into_iter()
+        .chain([(Level::High, 250), (Level::Low, 10000)])
+        .collect();
+
+    // noise before the frame is dropped together with the gap ending it
+    for pulse in [
+        (Level::High, 100),
+        (Level::Low, 200),
+        (Level::High, 50),
+        (Level::Low, 8000),
+    ] {
+        assert!(splitter.push(pulse).is_none());
+    }
+
+    let mut frames = vec![];
+    for &pulse in frame.iter().chain(frame.iter()) {
+        frames.extend(splitter.push(pulse));
+    }
+
+    assert!(frames == vec![frame.clone(), frame]);
+}
@@ -0,0 +1,164 @@
+use crate::pulse::{Jitter, Pulse, PulseSink};
+use crate::realtime::PrecisePin;
+use rppal::gpio::{self, Gpio};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+
+// All protocols share one radio. Instead of locking the pin around single
+// pulses, the sink is handed to a worker thread that sends whole frames in
+// the order they were submitted, so frames from concurrent requests can
+// never interleave.
+
+#[derive(Debug)]
+pub enum TransmitError {
+    Gpio(gpio::Error),
+    /// The sink panicked while sending the frame. The worker carries on with
+    /// the next one.
+    Failed,
+    /// The worker is gone and the frame was never sent.
+    Stopped,
+}
+
+impl fmt::Display for TransmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransmitError::Gpio(err) => write!(f, "GPIO error ({})", err),
+            TransmitError::Failed => write!(f, "Sending the frame failed"),
+            TransmitError::Stopped => write!(f, "Transmitter has stopped"),
+        }
+    }
+}
+
+impl std::error::Error for TransmitError {}
+
+impl From<gpio::Error> for TransmitError {
+    fn from(err: gpio::Error) -> TransmitError {
+        TransmitError::Gpio(err)
+    }
+}
+
+struct Job {
+    frame: Vec<Pulse>,
+    done: mpsc::Sender<Result<Jitter, TransmitError>>,
+}
+
+/// Handle to the transmitter worker. Clones share the same queue.
+#[derive(Clone)]
+pub struct Transmitter {
+    queue: mpsc::Sender<Job>,
+}
+
+/// Completion handle for a submitted frame.
+pub struct Transmission {
+    done: mpsc::Receiver<Result<Jitter, TransmitError>>,
+}
+
+impl Transmitter {
+    pub fn spawn<S: PulseSink + Send + 'static>(mut sink: S) -> Transmitter {
+        let (queue, jobs) = mpsc::channel::<Job>();
+        thread::Builder::new()
+            .name("transmitter".to_string())
+            .spawn(move || {
+                for job in jobs {
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| sink.send(&job.frame)))
+                        .map_err(|_| TransmitError::Failed);
+                    // the caller may not be waiting for the result
+                    let _ = job.done.send(result);
+                }
+            })
+            .unwrap();
+        Transmitter { queue }
+    }
+
+    /// Transmitter on a GPIO output pin of the Pi, with precise timing.
+    pub fn open(bcm_pin: u8) -> Result<Transmitter, TransmitError> {
+        let pin = Gpio::new()?.get(bcm_pin)?.into_output();
+        Ok(Transmitter::spawn(PrecisePin::new(pin)))
+    }
+
+    pub fn submit(&self, frame: Vec<Pulse>) -> Transmission {
+        let (done, receiver) = mpsc::channel();
+        // if the worker is gone the job is dropped, which wait reports
+        let _ = self.queue.send(Job { frame, done });
+        Transmission { done: receiver }
+    }
+}
+
+impl Transmission {
+    /// Blocks until the frame has been sent.
+    pub fn wait(self) -> Result<Jitter, TransmitError> {
+        self.done.recv().unwrap_or(Err(TransmitError::Stopped))
+    }
+}
+
+#[test]
+fn test_frames_are_not_interleaved() {
+    use crate::pulse::{Level, Recorder};
+
+    let recorder = Recorder::new();
+    let transmitter = Transmitter::spawn(recorder.clone());
+
+    let senders: Vec<_> = (1..=4u64)
+        .map(|n| {
+            let transmitter = transmitter.clone();
+            thread::spawn(move || {
+                let frame = [(Level::High, n), (Level::Low, n)].repeat(50);
+                transmitter.submit(frame).wait()
+            })
+        })
+        .collect();
+    for sender in senders {
+        assert!(sender.join().unwrap().unwrap().edges == 100);
+    }
+
+    let pulses = recorder.pulses();
+    assert!(pulses.len() == 400);
+    for frame in pulses.chunks(100) {
+        assert!(frame.iter().all(|p| p.1 == frame[0].1));
+    }
+}
+
+#[test]
+fn test_frames_are_sent_in_order() {
+    use crate::pulse::{Level, Recorder};
+
+    let recorder = Recorder::new();
+    let transmitter = Transmitter::spawn(recorder.clone());
+
+    let first = transmitter.submit(vec![(Level::High, 1)]);
+    let second = transmitter.submit(vec![(Level::High, 2)]);
+    second.wait().unwrap();
+    first.wait().unwrap();
+
+    assert!(recorder.pulses() == vec![(Level::High, 1), (Level::High, 2)]);
+}
+
+#[test]
+fn test_failed_frame_does_not_stop_the_worker() {
+    use crate::pulse::{Level, Recorder};
+
+    struct FlakySink(Recorder);
+
+    impl PulseSink for FlakySink {
+        fn high(&mut self, duration_us: u64) {
+            assert!(duration_us != 13, "radio gone");
+            self.0.high(duration_us);
+        }
+
+        fn low(&mut self, duration_us: u64) {
+            self.0.low(duration_us);
+        }
+    }
+
+    let recorder = Recorder::new();
+    let transmitter = Transmitter::spawn(FlakySink(recorder.clone()));
+
+    let failed = transmitter.submit(vec![(Level::High, 13)]).wait();
+    let sent = transmitter.submit(vec![(Level::High, 1)]).wait();
+
+    assert!(matches!(failed, Err(TransmitError::Failed)));
+    assert!(sent.unwrap().edges == 1);
+    assert!(recorder.pulses() == vec![(Level::High, 1)]);
+}
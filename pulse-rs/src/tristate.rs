@@ -0,0 +1,202 @@
+use crate::pulse::{Level, Pulse, Timing};
+use std::fmt;
+
+// PT2262 style encoders send every symbol as two pulse pairs, each pair
+// either narrow (short high, long low) or wide (long high, short low):
+//
+//   0: narrow narrow
+//   1: wide wide
+//   F: narrow wide
+//   Q: wide narrow, not sent by the PT2262 itself but used by some blinds
+//
+// Every frame also has a sync pair, sent before or after the symbols
+// depending on the protocol.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriState {
+    Zero,
+    One,
+    Float,
+    Q,
+}
+
+impl TriState {
+    pub fn from_char(c: char) -> Option<TriState> {
+        match c {
+            '0' => Some(TriState::Zero),
+            '1' => Some(TriState::One),
+            'F' => Some(TriState::Float),
+            'Q' => Some(TriState::Q),
+            _ => None,
+        }
+    }
+
+    // true for wide pairs
+    fn pairs(&self) -> (bool, bool) {
+        match *self {
+            TriState::Zero => (false, false),
+            TriState::One => (true, true),
+            TriState::Float => (false, true),
+            TriState::Q => (true, false),
+        }
+    }
+}
+
+impl fmt::Display for TriState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TriState::Zero => write!(f, "0"),
+            TriState::One => write!(f, "1"),
+            TriState::Float => write!(f, "F"),
+            TriState::Q => write!(f, "Q"),
+        }
+    }
+}
+
+/// Parses a code written with the symbols 0, 1, F and Q.
+pub fn parse(code: &str) -> Option<Vec<TriState>> {
+    code.chars().map(TriState::from_char).collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncPosition {
+    Leading,
+    Trailing,
+}
+
+/// A tri-state protocol. Short, long and the sync pair are multiples of the
+/// pulse length in timing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Protocol {
+    pub short: u64,
+    pub long: u64,
+    pub sync: (u64, u64),
+    pub sync_position: SyncPosition,
+    pub timing: Timing,
+}
+
+pub const PT2262: Protocol = Protocol {
+    short: 1,
+    long: 3,
+    sync: (1, 31),
+    sync_position: SyncPosition::Trailing,
+    timing: Timing {
+        pulse_length: 350,
+        repeats: 10,
+        frame_gap: 0,
+    },
+};
+
+/// Encodes a code into the pulses sent on air, with the sync and the frame
+/// gap of every repeat. Nothing is transmitted.
+pub fn encode(code: &[TriState], protocol: &Protocol) -> Vec<Pulse> {
+    let t = protocol.timing.pulse_length;
+    let mut frame = vec![];
+    for _ in 0..protocol.timing.repeats {
+        if protocol.sync_position == SyncPosition::Leading {
+            push_pair(&mut frame, t, protocol.sync);
+        }
+        for symbol in code {
+            let (first, second) = symbol.pairs();
+            for wide in [first, second] {
+                match wide {
+                    true => push_pair(&mut frame, t, (protocol.long, protocol.short)),
+                    false => push_pair(&mut frame, t, (protocol.short, protocol.long)),
+                }
+            }
+        }
+        if protocol.sync_position == SyncPosition::Trailing {
+            push_pair(&mut frame, t, protocol.sync);
+        }
+        if let Some((Level::Low, gap)) = frame.last_mut() {
+            *gap += protocol.timing.frame_gap;
+        }
+    }
+    frame
+}
+
+fn push_pair(frame: &mut Vec<Pulse>, t: u64, (high, low): (u64, u64)) {
+    frame.push((Level::High, t * high));
+    frame.push((Level::Low, t * low));
+}
+
+/// Reads one symbol from its two pulse pairs, t being the pulse length
+/// measured on the received frame.
+pub fn read_symbol(symbol: &[Pulse], t: u64, protocol: &Protocol) -> Option<TriState> {
+    let threshold = (protocol.short + protocol.long) * t / 2;
+    let mut pairs = symbol.chunks(2).map(|pair| match *pair {
+        // the low of the last pair runs into the gap after the frame
+        [(Level::High, high), (Level::Low, low)]
+            if high >= threshold && (low < threshold || low >= 2 * protocol.long * t) =>
+        {
+            Some(true)
+        }
+        [(Level::High, high), (Level::Low, low)] if high < threshold && low >= threshold => {
+            Some(false)
+        }
+        // the frame may end before the low of the last pair does
+        [(Level::High, high)] => Some(high >= threshold),
+        _ => None,
+    });
+    match (pairs.next()??, pairs.next()??) {
+        (false, false) => Some(TriState::Zero),
+        (true, true) => Some(TriState::One),
+        (false, true) => Some(TriState::Float),
+        (true, false) => Some(TriState::Q),
+    }
+}
+
+#[test]
+fn test_parse_and_display() {
+    let code = parse("01FQ").unwrap();
+
+    assert!(code == vec![TriState::Zero, TriState::One, TriState::Float, TriState::Q]);
+    assert!(code.iter().map(|s| s.to_string()).collect::<String>() == "01FQ");
+    assert!(parse("01X").is_none());
+}
+
+#[test]
+fn test_encode_pt2262() {
+    use Level::{High, Low};
+    let protocol = Protocol {
+        timing: Timing {
+            pulse_length: 100,
+            repeats: 2,
+            frame_gap: 1000,
+        },
+        ..PT2262
+    };
+
+    let frame = encode(&parse("0F").unwrap(), &protocol);
+
+    let narrow = [(High, 100), (Low, 300)];
+    let wide = [(High, 300), (Low, 100)];
+    let mut expected = vec![];
+    for _ in 0..2 {
+        expected.extend(narrow.repeat(3));
+        expected.extend(wide);
+        expected.extend([(High, 100), (Low, 3100 + 1000)]);
+    }
+    assert!(frame == expected);
+}
+
+#[test]
+fn test_read_symbol_round_trip() {
+    let protocol = Protocol {
+        timing: Timing {
+            repeats: 1,
+            ..PT2262.timing
+        },
+        ..PT2262
+    };
+    let code = parse("01FQ").unwrap();
+
+    let frame = encode(&code, &protocol);
+
+    let decoded: Option<Vec<TriState>> = frame
+        .chunks(4)
+        .take(code.len())
+        .map(|symbol| read_symbol(symbol, 350, &protocol))
+        .collect();
+    assert!(decoded == Some(code));
+}
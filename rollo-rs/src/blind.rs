@@ -0,0 +1,310 @@
+use crate::rollo::{Direction, Rollo};
+use pulse_rs::scheduler::Transmission;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The motors do not report where they are, so the position is estimated from
+// how long the blind has been moving. Positions are percent lowered, 0 is
+// fully up and 100 fully down. Every full move to an end stop puts the
+// estimate right again.
+//
+// Moves to a position in between are stopped by a timer thread sending PAUSE
+// when the blind should be there. Any later press drops the stop, so nothing
+// waits on the blind while it moves and it can always be stopped early.
+
+/// Time the blind needs to travel all the way in either direction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TravelTimes {
+    pub up: Duration,
+    pub down: Duration,
+}
+
+struct Movement {
+    direction: Direction,
+    from: u8,
+    started: Instant,
+    stop: Option<Stop>,
+}
+
+struct Stop {
+    at: u8,
+    deadline: Instant,
+    // dropping it cancels the timer
+    _cancel: mpsc::Sender<()>,
+}
+
+pub struct Blind {
+    rollo: Rollo,
+    travel_times: TravelTimes,
+    position: u8,
+    movement: Option<Movement>,
+}
+
+impl Blind {
+    /// A blind standing still at the given position, usually the last one
+    /// saved.
+    pub fn new(rollo: Rollo, travel_times: TravelTimes, position: u8) -> Blind {
+        Blind {
+            rollo,
+            travel_times,
+            position: position.min(100),
+            movement: None,
+        }
+    }
+
+    pub fn rollo(&self) -> &Rollo {
+        &self.rollo
+    }
+
+    pub fn travel_times(&self) -> TravelTimes {
+        self.travel_times
+    }
+
+    /// Estimated position right now.
+    pub fn position(&self) -> u8 {
+        match &self.movement {
+            Some(Movement {
+                stop: Some(stop), ..
+            }) if Instant::now() >= stop.deadline => stop.at,
+            Some(movement) => self.position_after(movement, movement.started.elapsed()),
+            None => self.position,
+        }
+    }
+
+    /// Position the blind comes to rest at unless it is stopped before.
+    pub fn destination(&self) -> u8 {
+        match self.movement {
+            Some(Movement {
+                stop: Some(Stop { at, .. }),
+                ..
+            }) => at,
+            Some(Movement {
+                direction: Direction::UP,
+                ..
+            }) => 0,
+            Some(Movement {
+                direction: Direction::DOWN,
+                ..
+            }) => 100,
+            _ => self.position,
+        }
+    }
+
+    /// Sends a button press and keeps track of the movement it starts or
+    /// stops.
+    pub fn send(&mut self, direction: Direction) -> Transmission {
+        self.record(direction);
+        self.rollo.send(direction)
+    }
+
+    /// Keeps track of a button press that reached the blind some other way,
+    /// such as a command to all channels of the remote.
+    pub fn record(&mut self, direction: Direction) {
+        self.position = self.position();
+        self.movement = match direction {
+            Direction::PAUSE => None,
+            direction => Some(Movement {
+                direction,
+                from: self.position,
+                started: Instant::now(),
+                stop: None,
+            }),
+        };
+    }
+
+    /// Starts moving the blind to the given position and has it stopped there
+    /// without waiting for it. Moves to either end are left to the end stops.
+    pub fn move_to(&mut self, target: u8) -> Option<Transmission> {
+        let target = target.min(100);
+        let current = self.position();
+        let (direction, travel_time) = match target {
+            _ if target == current && self.destination() == current => return None,
+            0 => return Some(self.send(Direction::UP)),
+            100 => return Some(self.send(Direction::DOWN)),
+            _ if target < current => (Direction::UP, self.travel_times.up),
+            _ => (Direction::DOWN, self.travel_times.down),
+        };
+        let distance = (target as i32 - current as i32).unsigned_abs();
+        // the motor starts on the first repeat of the frame and stops on the
+        // first repeat of the pause, so timing from the submission of one to
+        // the submission of the other cancels the delays out
+        let moving = self.send(direction);
+        if let Some(movement) = &mut self.movement {
+            let deadline = movement.started + travel_time * distance / 100;
+            let (cancel, cancelled) = mpsc::channel::<()>();
+            let rollo = self.rollo.clone();
+            thread::spawn(move || {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                if let Err(RecvTimeoutError::Timeout) = cancelled.recv_timeout(timeout) {
+                    // nobody is around to hear about a failure, the estimate
+                    // is put right by the next move to an end
+                    let _ = rollo.send(Direction::PAUSE).wait();
+                }
+            });
+            movement.stop = Some(Stop {
+                at: target,
+                deadline,
+                _cancel: cancel,
+            });
+        }
+        Some(moving)
+    }
+
+    fn position_after(&self, movement: &Movement, elapsed: Duration) -> u8 {
+        let travel_time = match movement.direction {
+            Direction::UP => self.travel_times.up,
+            _ => self.travel_times.down,
+        };
+        let moved = (100 * elapsed.as_millis() / travel_time.as_millis().max(1)).min(100) as u8;
+        match movement.direction {
+            Direction::UP => movement.from.saturating_sub(moved),
+            _ => (movement.from + moved).min(100),
+        }
+    }
+}
+
+#[cfg(test)]
+const TRAVEL_TIMES: TravelTimes = TravelTimes {
+    up: Duration::from_millis(400),
+    down: Duration::from_millis(200),
+};
+
+#[test]
+fn test_move_to_sends_direction_then_pause() {
+    use crate::rollo::{encode, DEFAULT_TIMING};
+    use pulse_rs::pulse::Recorder;
+    use pulse_rs::scheduler::Transmitter;
+
+    let recorder = Recorder::new();
+    let rollo = Rollo::new(
+        "FQ1Q011000Q00F000".parse().unwrap(),
+        Transmitter::spawn(recorder.clone()),
+    );
+    let mut blind = Blind::new(rollo, TRAVEL_TIMES, 0);
+
+    blind.move_to(40).unwrap().wait().unwrap();
+
+    assert!(blind.destination() == 40);
+    let code = blind.rollo().code();
+    let mut expected = encode(&code, Direction::DOWN, &DEFAULT_TIMING);
+    expected.extend(encode(&code, Direction::PAUSE, &DEFAULT_TIMING));
+    // the timer sends the pause whenever it gets to run
+    let waited = Instant::now();
+    while recorder.pulses() != expected && waited.elapsed() < Duration::from_secs(10) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(recorder.pulses() == expected);
+    assert!(blind.position() == 40);
+}
+
+#[test]
+fn test_move_up_uses_up_travel_time() {
+    use pulse_rs::pulse::Recorder;
+    use pulse_rs::scheduler::Transmitter;
+
+    let rollo = Rollo::new(
+        "FQ1Q011000Q00F000".parse().unwrap(),
+        Transmitter::spawn(Recorder::new()),
+    );
+    let mut blind = Blind::new(rollo, TRAVEL_TIMES, 60);
+
+    blind.move_to(35);
+
+    // 25% of 400 ms
+    let movement = blind.movement.as_ref().unwrap();
+    let stop = movement.stop.as_ref().unwrap();
+    assert!(movement.direction == Direction::UP);
+    assert!(stop.deadline == movement.started + Duration::from_millis(100));
+    assert!(blind.position_after(movement, Duration::from_millis(100)) == 35);
+    assert!(blind.destination() == 35);
+}
+
+#[test]
+fn test_move_to_current_position_sends_nothing() {
+    use pulse_rs::pulse::Recorder;
+    use pulse_rs::scheduler::Transmitter;
+
+    let recorder = Recorder::new();
+    let rollo = Rollo::new(
+        "FQ1Q011000Q00F000".parse().unwrap(),
+        Transmitter::spawn(recorder.clone()),
+    );
+    let mut blind = Blind::new(rollo, TRAVEL_TIMES, 35);
+
+    assert!(blind.move_to(35).is_none());
+    assert!(blind.movement.is_none());
+}
+
+#[test]
+fn test_pause_cancels_move_to() {
+    use crate::rollo::{encode, DEFAULT_TIMING};
+    use pulse_rs::pulse::Recorder;
+    use pulse_rs::scheduler::Transmitter;
+
+    let recorder = Recorder::new();
+    let rollo = Rollo::new(
+        "FQ1Q011000Q00F000".parse().unwrap(),
+        Transmitter::spawn(recorder.clone()),
+    );
+    let travel_times = TravelTimes {
+        up: Duration::from_secs(3600),
+        down: Duration::from_secs(3600),
+    };
+    let mut blind = Blind::new(rollo, travel_times, 0);
+
+    blind.move_to(80);
+    blind.send(Direction::PAUSE).wait().unwrap();
+
+    // the stop and with it the timer are gone
+    assert!(blind.movement.is_none());
+    assert!(blind.destination() == blind.position());
+    let code = blind.rollo().code();
+    let mut expected = encode(&code, Direction::DOWN, &DEFAULT_TIMING);
+    expected.extend(encode(&code, Direction::PAUSE, &DEFAULT_TIMING));
+    assert!(recorder.pulses() == expected);
+}
+
+#[test]
+fn test_move_to_end_leaves_blind_moving() {
+    use pulse_rs::pulse::Recorder;
+    use pulse_rs::scheduler::Transmitter;
+
+    let rollo = Rollo::new(
+        "FQ1Q011000Q00F000".parse().unwrap(),
+        Transmitter::spawn(Recorder::new()),
+    );
+    let mut blind = Blind::new(rollo, TRAVEL_TIMES, 20);
+
+    assert!(blind.move_to(100).is_some());
+
+    let movement = blind.movement.as_ref().unwrap();
+    assert!(movement.stop.is_none());
+    assert!(blind.destination() == 100);
+    // 20% of 200 ms
+    assert!(blind.position_after(movement, Duration::from_millis(40)) == 40);
+    assert!(blind.position_after(movement, Duration::from_millis(200)) == 100);
+}
+
+#[test]
+fn test_pause_keeps_estimated_position() {
+    use pulse_rs::pulse::Recorder;
+    use pulse_rs::scheduler::Transmitter;
+
+    let rollo = Rollo::new(
+        "FQ1Q011000Q00F000".parse().unwrap(),
+        Transmitter::spawn(Recorder::new()),
+    );
+    let mut blind = Blind::new(rollo, TRAVEL_TIMES, 100);
+
+    blind.record(Direction::UP);
+    // 100 ms of 400 ms up is 25%
+    let movement = blind.movement.as_ref().unwrap();
+    assert!(blind.position_after(movement, Duration::from_millis(100)) == 75);
+    blind.record(Direction::PAUSE);
+
+    let position = blind.position();
+    assert!(blind.movement.is_none());
+    assert!(blind.position == position);
+    assert!(blind.destination() == position);
+}
@@ -1 +1,2 @@
+pub mod blind;
 pub mod rollo;
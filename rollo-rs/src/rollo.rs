@@ -1,75 +1,560 @@
-use rppal::gpio::OutputPin;
-use std::sync::{Arc, Mutex};
+use pulse_rs::pulse::{Level, Pulse, Timing};
+use pulse_rs::scheduler::{Transmission, TransmitError, Transmitter};
+use pulse_rs::tristate::{self, Protocol, SyncPosition, TriState};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const DEFAULT_TIMING: Timing = Timing {
+    pulse_length: 250,
+    repeats: 6,
+    frame_gap: 0,
+};
+pub const CODE_LENGTH: usize = 17;
+const DIRECTION_LENGTH: usize = 3;
 
-const PULSE_LENGTH: u64 = 250;
 #[derive(Clone)]
-pub struct Rollo<'a> {
-    code: &'a str,
-    pin: Arc<Mutex<OutputPin>>,
+pub struct Rollo {
+    code: RolloCode,
+    timing: Timing,
+    transmitter: Transmitter,
 }
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Direction {
     UP,
     PAUSE,
     DOWN,
 }
 
-impl Rollo<'_> {
-    pub fn new(code: &str, pin: Arc<Mutex<OutputPin>>) -> Rollo {
-        Rollo { code, pin }
+/// The code of a remote, written as 17 tri-state symbols such as
+/// FQ1Q011000Q00F000.
+///
+/// Every channel of a multi-channel remote, the one for
+/// all blinds included, sends a code of its own, so each is recorded with
+/// `rollo-tester listen` rather than worked out from another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RolloCode([TriState; CODE_LENGTH]);
+
+#[derive(Debug)]
+pub enum RolloError {
+    InvalidCode(String),
+    InvalidCodeLength(usize),
+    Transmit(TransmitError),
+}
+
+impl fmt::Display for RolloError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RolloError::InvalidCode(code) => {
+                write!(f, "Code {} may only contain 0, 1, F and Q", code)
+            }
+            RolloError::InvalidCodeLength(length) => {
+                write!(f, "Code has {} symbols, expected {}", length, CODE_LENGTH)
+            }
+            RolloError::Transmit(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for RolloError {}
+
+impl From<TransmitError> for RolloError {
+    fn from(err: TransmitError) -> RolloError {
+        RolloError::Transmit(err)
+    }
+}
+
+impl RolloCode {
+    pub fn new(symbols: [TriState; CODE_LENGTH]) -> RolloCode {
+        RolloCode(symbols)
+    }
+
+    pub fn symbols(&self) -> &[TriState; CODE_LENGTH] {
+        &self.0
+    }
+}
+
+impl FromStr for RolloCode {
+    type Err = RolloError;
+
+    fn from_str(code: &str) -> Result<RolloCode, RolloError> {
+        let symbols =
+            tristate::parse(code).ok_or_else(|| RolloError::InvalidCode(code.to_string()))?;
+        let length = symbols.len();
+        symbols
+            .try_into()
+            .map(RolloCode)
+            .map_err(|_| RolloError::InvalidCodeLength(length))
+    }
+}
+
+impl fmt::Display for RolloCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for symbol in self.0.iter() {
+            write!(f, "{}", symbol)?;
+        }
+        Ok(())
+    }
+}
+
+impl Rollo {
+    pub fn new(code: RolloCode, transmitter: Transmitter) -> Rollo {
+        Rollo {
+            code,
+            timing: DEFAULT_TIMING,
+            transmitter,
+        }
+    }
+
+    pub fn with_timing(mut self, timing: Timing) -> Rollo {
+        self.timing = timing;
+        self
     }
 
-    fn send_t0(&self) {
-        self.transmit(1, 3);
-        self.transmit(1, 3);
+    pub fn with_pulse_length(mut self, pulse_length: u64) -> Rollo {
+        self.timing.pulse_length = pulse_length;
+        self
     }
 
-    fn send_tf(&self) {
-        self.transmit(1, 3);
-        self.transmit(3, 1);
+    pub fn with_repeats(mut self, repeats: usize) -> Rollo {
+        self.timing.repeats = repeats;
+        self
     }
 
-    pub fn send(&self, direction: Direction) {
-        for _ in 0..6 {
-            self.send_sync();
-            let full = self.code.to_string()
-                + match direction {
-                    Direction::UP => "F0F",
-                    Direction::PAUSE => "FFF",
-                    Direction::DOWN => "101",
-                };
-            for c in full.chars() {
-                match c {
-                    '0' => self.send_t0(),
-                    'F' => self.send_tf(),
-                    '1' => self.send_t1(),
-                    'Q' => self.send_qq(),
-                    _ => panic!("Should not happen!"),
-                }
+    pub fn with_frame_gap(mut self, frame_gap: u64) -> Rollo {
+        self.timing.frame_gap = frame_gap;
+        self
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    pub fn code(&self) -> RolloCode {
+        self.code
+    }
+
+    pub fn send(&self, direction: Direction) -> Transmission {
+        let frame = encode(&self.code, direction, &self.timing);
+        self.transmitter.submit(frame)
+    }
+
+    /// Teaches the code to a motor in programming mode. up_and_down is what
+    /// the remote being cloned sends while UP and DOWN are held together.
+    pub fn pair(&self, up_and_down: Buttons) -> Transmission {
+        self.program(&pair_sequence(up_and_down))
+    }
+
+    /// Makes a motor in programming mode forget the code.
+    pub fn unpair(&self, up_and_down: Buttons) -> Transmission {
+        self.program(&unpair_sequence(up_and_down))
+    }
+
+    // A press goes out one repeat per frame and the gap between presses as
+    // short silences, so other frames still get through while a motor is
+    // being programmed.
+    fn program(&self, sequence: &[Press]) -> Transmission {
+        let mut frames = vec![];
+        for (i, press) in sequence.iter().enumerate() {
+            if i > 0 {
+                frames.extend(silence(RELEASE_GAP));
             }
+            frames.extend(encode_press(&self.code, press, &self.timing));
+        }
+        let mut transmissions: Vec<Transmission> = frames
+            .into_iter()
+            .map(|frame| self.transmitter.submit(frame))
+            .collect();
+        // frames go out in order, so the last one is done when all are
+        transmissions.pop().unwrap()
+    }
+}
+
+/// The symbols after the code, saying which buttons are held. UP and DOWN
+/// held together send symbols of their own that are not one of the
+/// directions, `rollo-tester listen` shows them.
+pub type Buttons = [TriState; DIRECTION_LENGTH];
+
+/// Buttons held down for a while.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Press {
+    pub buttons: Buttons,
+    pub hold: Duration,
+}
+
+// The motors learn and forget codes only in programming mode, which they
+// enter after power-up or when the program button of a remote they already
+// know is held. Holding PAUSE until the motor jogs and then UP and DOWN
+// together pairs the code of the remote. Holding UP and DOWN until the motor
+// jogs twice and then PAUSE unpairs it. There is no datasheet for the motors
+// to take the hold times from, they are chosen to outlast the jogs.
+pub fn pair_sequence(up_and_down: Buttons) -> [Press; 2] {
+    [
+        Press {
+            buttons: Direction::PAUSE.buttons(),
+            hold: Duration::from_secs(2),
+        },
+        Press {
+            buttons: up_and_down,
+            hold: Duration::from_secs(2),
+        },
+    ]
+}
+
+pub fn unpair_sequence(up_and_down: Buttons) -> [Press; 2] {
+    [
+        Press {
+            buttons: up_and_down,
+            hold: Duration::from_secs(6),
+        },
+        Press {
+            buttons: Direction::PAUSE.buttons(),
+            hold: Duration::from_secs(2),
+        },
+    ]
+}
+
+const RELEASE_GAP: Duration = Duration::from_secs(1);
+const SILENCE_FRAME: Duration = Duration::from_millis(100);
+
+/// The blind remotes are tri-state encoders with a long leading sync.
+pub const PROTOCOL: Protocol = Protocol {
+    short: 1,
+    long: 3,
+    sync: (18, 6),
+    sync_position: SyncPosition::Leading,
+    timing: DEFAULT_TIMING,
+};
+
+impl Direction {
+    pub fn buttons(&self) -> Buttons {
+        use TriState::{Float, One, Zero};
+        match *self {
+            Direction::UP => [Float, Zero, Float],
+            Direction::PAUSE => [Float, Float, Float],
+            Direction::DOWN => [One, Zero, One],
         }
-        std::thread::sleep(std::time::Duration::from_micros(PULSE_LENGTH));
     }
+}
+
+/// Encodes the code and direction into the pulses sent on air, including the
+/// sync of every repeat. Nothing is transmitted.
+pub fn encode(code: &RolloCode, direction: Direction, timing: &Timing) -> Vec<Pulse> {
+    let protocol = Protocol {
+        timing: *timing,
+        ..PROTOCOL
+    };
+    let mut frame = tristate::encode(&symbols(code, direction.buttons()), &protocol);
+    frame.push((Level::Low, timing.pulse_length));
+    frame
+}
+
+/// Encodes a press as a remote sends it, repeating the frame for as long as
+/// the buttons are held. Every repeat is a frame of its own.
+pub fn encode_press(code: &RolloCode, press: &Press, timing: &Timing) -> Vec<Vec<Pulse>> {
+    let protocol = Protocol {
+        timing: Timing {
+            repeats: 1,
+            ..*timing
+        },
+        ..PROTOCOL
+    };
+    let repeat = tristate::encode(&symbols(code, press.buttons), &protocol);
+    let repeat_us: u64 = repeat.iter().map(|&(_, duration)| duration).sum();
+    let count = press.hold.as_micros() as u64 / repeat_us.max(1) + 1;
+    let mut frames = vec![repeat; count as usize];
+    if let Some(last) = frames.last_mut() {
+        last.push((Level::Low, timing.pulse_length));
+    }
+    frames
+}
 
-    fn send_sync(&self) {
-        self.transmit(18, 6);
+fn silence(duration: Duration) -> Vec<Vec<Pulse>> {
+    let frame_us = SILENCE_FRAME.as_micros() as u64;
+    let mut left_us = duration.as_micros() as u64;
+    let mut frames = vec![];
+    while left_us > 0 {
+        frames.push(vec![(Level::Low, left_us.min(frame_us))]);
+        left_us = left_us.saturating_sub(frame_us);
     }
+    frames
+}
+
+fn symbols(code: &RolloCode, buttons: Buttons) -> Vec<TriState> {
+    let mut symbols = code.symbols().to_vec();
+    symbols.extend(buttons);
+    symbols
+}
+
+/// A frame received from a remote, the code identifying the remote and
+/// channel and the button that was pressed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Command {
+    pub code: RolloCode,
+    pub direction: Direction,
+}
+
+/// Finds the first complete frame in captured pulses and decodes it.
+pub fn decode(pulses: &[Pulse]) -> Option<Command> {
+    (0..pulses.len()).find_map(|start| {
+        let (code, buttons) = decode_frame(&pulses[start..])?;
+        let direction = [Direction::UP, Direction::PAUSE, Direction::DOWN]
+            .into_iter()
+            .find(|direction| direction.buttons() == buttons)?;
+        Some(Command { code, direction })
+    })
+}
 
-    fn transmit(&self, high_pulses: u64, low_pulses: u64) {
-        let mut pin = self.pin.lock().unwrap();
-        pin.set_high();
-        std::thread::sleep(std::time::Duration::from_micros(PULSE_LENGTH * high_pulses));
-        pin.set_low();
-        std::thread::sleep(std::time::Duration::from_micros(PULSE_LENGTH * low_pulses));
+/// Like decode, but also for frames of buttons that are not a direction.
+pub fn decode_buttons(pulses: &[Pulse]) -> Option<(RolloCode, Buttons)> {
+    (0..pulses.len()).find_map(|start| decode_frame(&pulses[start..]))
+}
+
+fn decode_frame(pulses: &[Pulse]) -> Option<(RolloCode, Buttons)> {
+    let (sync_high, sync_low, symbols) = match pulses {
+        [(Level::High, high), (Level::Low, low), symbols @ ..] => (*high, *low, symbols),
+        _ => return None,
+    };
+    let t = sync_low / 6;
+    if t == 0 || !(12 * t..24 * t).contains(&sync_high) {
+        return None;
     }
 
-    fn send_t1(&self) {
-        self.transmit(3, 1);
-        self.transmit(3, 1);
+    let mut full = vec![];
+    for symbol in symbols.chunks(4).take(CODE_LENGTH + DIRECTION_LENGTH) {
+        full.push(tristate::read_symbol(symbol, t, &PROTOCOL)?);
     }
+    if full.len() < CODE_LENGTH + DIRECTION_LENGTH {
+        return None;
+    }
+    Some((
+        RolloCode(full[..CODE_LENGTH].try_into().ok()?),
+        full[CODE_LENGTH..].try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+const CODE: &str = "FQ1Q011000Q00F000";
+
+#[test]
+fn test_send_pulses() {
+    use pulse_rs::pulse::Recorder;
+    use pulse_rs::scheduler::Transmitter;
+    use Level::{High, Low};
+    const PULSE_LENGTH: u64 = 250;
+
+    let recorder = Recorder::new();
+    let code = "0F1Q0F1Q0F1Q0F1Q0".parse().unwrap();
+    let rollo = Rollo::new(code, Transmitter::spawn(recorder.clone()));
+
+    rollo.send(Direction::DOWN).wait().unwrap();
 
-    fn send_qq(&self) {
-        self.transmit(3, 1);
-        self.transmit(1, 3);
+    let short = (High, PULSE_LENGTH);
+    let long = (High, 3 * PULSE_LENGTH);
+    let short_gap = (Low, PULSE_LENGTH);
+    let long_gap = (Low, 3 * PULSE_LENGTH);
+    let zero = [short, long_gap, short, long_gap];
+    let float = [short, long_gap, long, short_gap];
+    let one = [long, short_gap, long, short_gap];
+    let q = [long, short_gap, short, long_gap];
+
+    let mut frame = vec![(High, 18 * PULSE_LENGTH), (Low, 6 * PULSE_LENGTH)];
+    for _ in 0..4 {
+        frame.extend([zero, float, one, q].concat());
+    }
+    // last symbol of the code followed by DOWN
+    for symbol in [zero, one, zero, one] {
+        frame.extend(symbol);
     }
+    let mut expected = frame.repeat(6);
+    expected.push((Low, PULSE_LENGTH));
+
+    assert!(recorder.pulses() == expected);
+}
+
+#[test]
+fn test_encode_is_pure() {
+    const PULSE_LENGTH: u64 = 250;
+    let frame = encode(&CODE.parse().unwrap(), Direction::UP, &DEFAULT_TIMING);
+
+    // sync plus 20 symbols of four pulses each, repeated, and the final gap
+    assert!(frame.len() == 6 * (2 + 20 * 4) + 1);
+    assert!(frame[0] == (Level::High, 18 * PULSE_LENGTH));
+    assert!(frame == encode(&CODE.parse().unwrap(), Direction::UP, &DEFAULT_TIMING));
+    assert!(frame != encode(&CODE.parse().unwrap(), Direction::DOWN, &DEFAULT_TIMING));
+}
+
+#[test]
+fn test_rollo_outlives_code_and_moves_into_thread() {
+    use pulse_rs::pulse::Recorder;
+    use pulse_rs::scheduler::Transmitter;
+
+    let recorder = Recorder::new();
+    let rollo = {
+        let code = String::from(CODE);
+        Rollo::new(code.parse().unwrap(), Transmitter::spawn(recorder.clone()))
+    };
+
+    std::thread::spawn(move || rollo.send(Direction::PAUSE).wait().unwrap())
+        .join()
+        .unwrap();
+
+    assert!(recorder.pulses() == encode(&CODE.parse().unwrap(), Direction::PAUSE, &DEFAULT_TIMING));
+}
+
+#[test]
+fn test_configured_timing() {
+    use pulse_rs::pulse::Recorder;
+    use pulse_rs::scheduler::Transmitter;
+
+    let recorder = Recorder::new();
+    let rollo = Rollo::new(CODE.parse().unwrap(), Transmitter::spawn(recorder.clone()))
+        .with_pulse_length(200)
+        .with_repeats(3)
+        .with_frame_gap(5000);
+
+    rollo.send(Direction::UP).wait().unwrap();
+
+    let pulses = recorder.pulses();
+    let frame_length = 2 + 20 * 4;
+    assert!(pulses.len() == 3 * frame_length + 1);
+    assert!(pulses[0] == (Level::High, 18 * 200));
+    // F0F ends with a short gap, stretched by the frame gap
+    assert!(pulses[frame_length - 1] == (Level::Low, 200 + 5000));
+    assert!(pulses[frame_length] == (Level::High, 18 * 200));
+    assert!(pulses[3 * frame_length] == (Level::Low, 200));
+}
+
+#[test]
+fn test_decode_round_trip() {
+    for direction in [Direction::UP, Direction::PAUSE, Direction::DOWN] {
+        let frame = encode(&CODE.parse().unwrap(), direction, &DEFAULT_TIMING);
+
+        let command = decode(&frame).unwrap();
+
+        assert!(command.code.to_string() == CODE);
+        assert!(command.direction == direction);
+    }
+}
+
+#[test]
+fn test_decode_captured_frame() {
+    // a single repeat with stretched highs, shortened lows and no low after
+    // the last pair
+    let mut frame: Vec<Pulse> = encode(&CODE.parse().unwrap(), Direction::DOWN, &DEFAULT_TIMING)
+        .into_iter()
+        .skip(2 + 20 * 4)
+        .take(2 + 20 * 4)
+        .map(|(level, duration)| match level {
+            Level::High => (level, duration * 11 / 10 + 40),
+            Level::Low => (level, duration * 9 / 10),
+        })
+        .collect();
+    frame.pop();
+
+    let command = decode(&frame).unwrap();
+
+    assert!(command.code.to_string() == CODE);
+    assert!(command.direction == Direction::DOWN);
+}
+
+#[test]
+fn test_decode_rejects_noise() {
+    use Level::{High, Low};
+
+    assert!(decode(&[]).is_none());
+    assert!(decode(&[(High, 4500), (Low, 1500), (High, 250), (Low, 750)]).is_none());
+    // a truncated frame
+    let frame = encode(&CODE.parse().unwrap(), Direction::UP, &DEFAULT_TIMING);
+    assert!(decode(&frame[..60]).is_none());
+    // a code followed by an unknown button
+    let frame = tristate::encode(&tristate::parse("FQ1Q011000Q00F000QF0").unwrap(), &PROTOCOL);
+    assert!(decode(&frame).is_none());
+}
+
+// There is no capture of a real remote in the repo yet, `rollo-tester
+// record` saves one to check decode against.
+#[test]
+fn test_decode_replayed_capture() {
+    let code: RolloCode = CODE.parse().unwrap();
+    let frame = encode(&code, Direction::DOWN, &DEFAULT_TIMING);
+
+    let mut written = vec![];
+    pulse_rs::capture::write(&mut written, &frame).unwrap();
+    let replayed = pulse_rs::capture::read(&written[..]).unwrap();
+
+    assert!(replayed == frame);
+    assert!(
+        decode(&replayed)
+            == Some(Command {
+                code,
+                direction: Direction::DOWN
+            })
+    );
+}
+
+#[test]
+fn test_code_from_str_and_display() {
+    let code: RolloCode = CODE.parse().unwrap();
+
+    assert!(code.to_string() == CODE);
+    assert!(code.symbols()[..4] == [TriState::Float, TriState::Q, TriState::One, TriState::Q]);
+    assert!(matches!(
+        "FQ1Q0X1000Q00F000".parse::<RolloCode>(),
+        Err(RolloError::InvalidCode(code)) if code == "FQ1Q0X1000Q00F000"
+    ));
+    assert!(matches!(
+        "FQ1Q".parse::<RolloCode>(),
+        Err(RolloError::InvalidCodeLength(4))
+    ));
+    assert!(matches!(
+        "FQ1Q011000Q00F000F0F".parse::<RolloCode>(),
+        Err(RolloError::InvalidCodeLength(20))
+    ));
+}
+
+#[test]
+fn test_encode_press_holds_buttons() {
+    let code: RolloCode = CODE.parse().unwrap();
+    let up_and_down = tristate::parse("QF0").unwrap().try_into().unwrap();
+    let press = Press {
+        buttons: up_and_down,
+        hold: Duration::from_millis(500),
+    };
+
+    let frames = encode_press(&code, &press, &DEFAULT_TIMING);
+
+    let held_us: u64 =
+        frames.iter().flatten().map(|p| p.1).sum::<u64>() - DEFAULT_TIMING.pulse_length;
+    let repeat_us: u64 = frames[0].iter().map(|p| p.1).sum();
+    assert!(held_us >= 500_000 && held_us < 500_000 + repeat_us);
+    // one repeat a frame, all of the same buttons
+    for frame in frames.iter() {
+        assert!(frame[..frames[0].len()] == frames[0][..]);
+        assert!(decode_buttons(frame) == Some((code, up_and_down)));
+    }
+    assert!(frames.last().unwrap().last() == Some(&(Level::Low, DEFAULT_TIMING.pulse_length)));
+}
+
+#[test]
+fn test_pair_sends_sequence() {
+    use pulse_rs::pulse::Recorder;
+    use pulse_rs::scheduler::Transmitter;
+
+    let recorder = Recorder::new();
+    let rollo = Rollo::new(CODE.parse().unwrap(), Transmitter::spawn(recorder.clone()));
+    let up_and_down = tristate::parse("QF0").unwrap().try_into().unwrap();
+
+    rollo.pair(up_and_down).wait().unwrap();
+
+    let [pause, press] = pair_sequence(up_and_down);
+    let mut expected = encode_press(&rollo.code(), &pause, &DEFAULT_TIMING).concat();
+    expected.extend(silence(RELEASE_GAP).concat());
+    expected.extend(encode_press(&rollo.code(), &press, &DEFAULT_TIMING).concat());
+    assert!(recorder.pulses() == expected);
+    let command = decode(&recorder.pulses()).unwrap();
+    assert!(command.direction == Direction::PAUSE);
+    assert!(silence(RELEASE_GAP)
+        .iter()
+        .all(|frame| frame == &[(Level::Low, 100_000)]));
 }
@@ -1,20 +1,121 @@
+use nexa_rs::nexa;
+use pulse_rs::capture;
+use pulse_rs::receiver::Receiver;
+use pulse_rs::scheduler::Transmitter;
+use pulse_rs::tristate;
 use rollo_rs::rollo;
 use rppal::gpio::Gpio;
 use std::env;
-use std::sync::{Arc, Mutex};
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    println!("{0:?}", args);
-    let code = args[1].clone();
+fn open_receiver() -> Receiver {
+    const GPIO_RECEIVER: u8 = 27;
+    Receiver::new(
+        Gpio::new()
+            .unwrap()
+            .get(GPIO_RECEIVER)
+            .unwrap()
+            .into_input(),
+    )
+    .unwrap()
+}
+
+fn listen() {
+    let receiver = open_receiver();
+
+    println!("Press a button on the remote");
+    while let Some(frame) = receiver.recv_frame() {
+        if let Some(command) = rollo::decode(&frame) {
+            println!("Received {} {:?}", command.code, command.direction);
+        } else if let Some((code, buttons)) = rollo::decode_buttons(&frame) {
+            // such as UP and DOWN held together, for pair and unpair
+            let buttons: String = buttons.iter().map(|symbol| symbol.to_string()).collect();
+            println!("Received {} buttons {}", code, buttons);
+        }
+    }
+}
+
+// Nexa frames are recorded as well, so captures can be made for either crate.
+fn record(path: &str) {
+    let receiver = open_receiver();
+
+    println!("Press a button on the remote");
+    while let Some(frame) = receiver.recv_frame() {
+        let decoded = if let Some(command) = rollo::decode(&frame) {
+            format!("{} {:?}", command.code, command.direction)
+        } else if let Some(command) = nexa::decode(&frame) {
+            format!("{:?}", command)
+        } else {
+            continue;
+        };
+        capture::save(path, &frame).unwrap();
+        println!("Recorded {} to {}", decoded, path);
+        return;
+    }
+}
+
+fn open_rollo(code: &str) -> Option<rollo::Rollo> {
     const GPIO_LED: u8 = 17;
-    let pin = Arc::new(Mutex::new(
-        Gpio::new().unwrap().get(GPIO_LED).unwrap().into_output(),
-    ));
+    let code: rollo::RolloCode = match code.parse() {
+        Ok(code) => code,
+        Err(err) => {
+            println!("{}", err);
+            return None;
+        }
+    };
+    let transmitter = match Transmitter::open(GPIO_LED) {
+        Ok(transmitter) => transmitter,
+        Err(err) => {
+            println!("Could not open transmitter ({})", err);
+            return None;
+        }
+    };
 
     println!("Writing {}", code);
+    Some(rollo::Rollo::new(code, transmitter))
+}
+
+fn program(code: &str, up_and_down: &str, pair: bool) {
+    let up_and_down: rollo::Buttons =
+        match tristate::parse(up_and_down).and_then(|symbols| symbols.try_into().ok()) {
+            Some(buttons) => buttons,
+            None => {
+                println!("{} is not three symbols of 0, 1, F and Q", up_and_down);
+                return;
+            }
+        };
+    let device = match open_rollo(code) {
+        Some(device) => device,
+        None => return,
+    };
+
+    println!("Put the motor in programming mode and press enter");
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line).unwrap();
+
+    let transmission = match pair {
+        true => device.pair(up_and_down),
+        false => device.unpair(up_and_down),
+    };
+    match transmission.wait() {
+        Ok(_) => println!("Done"),
+        Err(err) => println!("Failed ({})", err),
+    }
+}
 
-    let device = rollo::Rollo::new(&code, pin);
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    println!("{0:?}", args);
+    match args[1].as_str() {
+        "listen" => return listen(),
+        "record" => return record(&args[2]),
+        "pair" => return program(&args[2], &args[3], true),
+        "unpair" => return program(&args[2], &args[3], false),
+        _ => {}
+    }
+    let device = match open_rollo(&args[1]) {
+        Some(device) => device.with_repeats(18),
+        None => return,
+    };
 
     let direction = match args[2].as_str() {
         "u" => rollo::Direction::UP,
@@ -22,8 +123,8 @@ fn main() {
         _ => rollo::Direction::PAUSE,
     };
 
-    for _ in 0..3 {
-        device.send(direction.clone());
+    match device.send(direction).wait() {
+        Ok(jitter) => println!("Sent ({})", jitter),
+        Err(err) => println!("Not sent ({})", err),
     }
-    println!("Sent");
 }